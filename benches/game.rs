@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use hadar::env::Direction;
+use hadar::game::Game;
+
+fn game() -> Game {
+    Game::parse(
+        r#"
+        . . . . . . . . . . .
+        . . . . . . . . o . .
+        . . . . . . . . . . .
+        . . . . . . . . . . .
+        . . . . . 0 < < . . .
+        . . . . . . . ^ . . .
+        . . . . . > > ^ . . .
+        . . . . . . . . . . .
+        . . 1 . . . . . . . .
+        . . ^ . . . . . . . .
+        . . ^ . . . . . . . ."#,
+    )
+    .unwrap()
+}
+
+/// Simulates the copies of a search that expands every move of the first snake.
+fn game_clone(c: &mut Criterion) {
+    let game = game();
+
+    c.bench_function("game clone", |b| {
+        b.iter(|| {
+            for d in Direction::all() {
+                let mut child = game.clone();
+                child.step(&[d, Direction::Up]);
+                black_box(&child);
+            }
+        })
+    });
+
+    c.bench_function("game clone_into", |b| {
+        let mut child = game.clone();
+        b.iter(|| {
+            for d in Direction::all() {
+                game.clone_into(&mut child);
+                child.step(&[d, Direction::Up]);
+                black_box(&child);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, game_clone);
+criterion_main!(benches);
@@ -1,96 +1,1324 @@
-use std::cell::RefCell;
-use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use rand::rngs::SmallRng;
 use rand::seq::IteratorRandom;
-use rand::SeedableRng;
+use tokio_util::sync::CancellationToken;
 
 use crate::env::*;
-use crate::game::Game;
-use crate::grid::CellT;
+use crate::eval::{HAZARD_WEIGHT, UNREACHABLE};
+use crate::game::{fallback_move_with, FallbackOrder, Game, START_HEALTH};
+use crate::grid::{CellT, Heuristic};
+use crate::util::AgentRng;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct StarAgent;
+#[serde(default)]
+pub struct StarAgent {
+    /// Heuristic of the path finding to the nearest food.
+    pub heuristic: Heuristic,
+    /// Skip food if the reachable area after eating it is smaller than the
+    /// grown snake.
+    pub food_space: bool,
+    /// Hunt the predicted heads of shorter snakes that are close.
+    pub aggressive: bool,
+    /// Only hunt if the first move traps the prey in an area that is smaller
+    /// than its body and does not risk losing a head to head.
+    pub guard_hunt: bool,
+    /// When hunting, race for the only reachable food of opponents at or
+    /// below this health, if the agent gets there first.
+    pub deny_food: Option<u8>,
+    /// Keep the targeted food over multiple turns until it becomes
+    /// unreachable or a much closer food appears.
+    /// The targets are kept by game id, so games without an id (like
+    /// playouts) always target the nearest food.
+    pub commit: bool,
+    /// Number of moves a food has to be closer than the committed target to
+    /// replace it, which prevents dithering between near-equal foods.
+    /// Without a margin, the food has to be at half the distance.
+    pub commit_margin: Option<usize>,
+    /// Target the food that is nearest to the centroid of this number of
+    /// nearest foods instead of the nearest one, which keeps the snake in
+    /// food clusters.
+    /// Takes precedence over [`StarAgent::commit`].
+    pub food_centroid: Option<usize>,
+    /// Number of turns at the start of a game in which the agent moves toward
+    /// the center instead of the nearest food.
+    pub opening_turns: usize,
+    /// Cost reduction (between 0 and 1) of food cells on the paths toward
+    /// targets that are no food, like the center or a hunted head, so that
+    /// the agent clips food on the way.
+    pub food_bias: f64,
+    /// Order of the last-resort moves.
+    pub fallback: FallbackOrder,
+    /// Below this health, the agent refuses moves into hazards (unless all
+    /// moves are hazardous).
+    /// Above it, hazards are entered if the path to the target saves more
+    /// than their health costs.
+    /// Without a tolerance, moves into hazards get a small penalty.
+    pub hazard_risk_tolerance: Option<u8>,
+    /// Expect the hazards to grow every this number of turns (like in
+    /// royale) and avoid paths through cells that become hazardous before
+    /// the snake arrives (see [`Game::hazard_forecast`]).
+    pub hazard_forecast: Option<usize>,
+    /// Number of opponent replies that are simulated to discard moves that
+    /// lose (see [`Game::survives_replies`]), zero disables the lookahead.
+    pub lookahead: u8,
+    /// Hard cap on the [`StarAgent::lookahead`], as every simulated turn
+    /// keeps another cloned game and multiplies the number of replies.
+    pub max_lookahead: u8,
+    /// Also move on boards above the maximum board size, instead of falling
+    /// back to random moves.
+    /// The food searches are still bounded by the timeout, but the other
+    /// heuristics scale with the board and might exceed it.
+    pub force: bool,
+    /// Committed food targets by game id and snake.
+    #[serde(skip)]
+    committed: Arc<Mutex<HashMap<(String, u8), Vec2D>>>,
+    /// Breaks ties between equally good moves.
+    #[serde(skip)]
+    rng: AgentRng,
+}
+
+/// Maximum number of moves to a predicted head for hunting it.
+const HUNT_DISTANCE: usize = 3;
+/// Number of A* nodes the food searches on oversized boards (see
+/// [`StarAgent::force`]) may expand per ms of the timeout.
+pub const NODES_PER_MS: usize = 1;
+/// Default of [`StarAgent::max_lookahead`].
+pub const MAX_LOOKAHEAD: u8 = 2;
+/// Maximum number of committed targets that are remembered.
+const MAX_COMMITTED: usize = 64;
+
+impl Default for StarAgent {
+    fn default() -> Self {
+        Self {
+            heuristic: Heuristic::default(),
+            food_space: true,
+            aggressive: false,
+            guard_hunt: false,
+            deny_food: None,
+            commit: false,
+            commit_margin: None,
+            food_centroid: None,
+            opening_turns: 0,
+            food_bias: 0.0,
+            fallback: FallbackOrder::default(),
+            hazard_risk_tolerance: None,
+            hazard_forecast: None,
+            lookahead: 0,
+            max_lookahead: MAX_LOOKAHEAD,
+            force: false,
+            committed: Arc::default(),
+            rng: AgentRng::default(),
+        }
+    }
+}
 
 impl StarAgent {
+    /// Uses a seeded random number generator for reproducible tie breaks.
+    pub fn seeded(self, seed: u64) -> Self {
+        Self {
+            rng: AgentRng::seeded(seed),
+            ..self
+        }
+    }
+
+    /// Generator of the random tie breaks.
+    pub(crate) fn rng(&self) -> &AgentRng {
+        &self.rng
+    }
+
+    /// Returns the problems of the config, like values that are out of range.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, health) in [
+            ("deny_food", self.deny_food),
+            ("hazard_risk_tolerance", self.hazard_risk_tolerance),
+        ] {
+            if let Some(health) = health.filter(|&h| h > START_HEALTH) {
+                problems.push(format!(
+                    "{name} of {health} is above the maximum health of {START_HEALTH}"
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.food_bias) {
+            problems.push(format!(
+                "food_bias of {} is not between 0 and 1",
+                self.food_bias
+            ));
+        }
+        if self.food_centroid == Some(0) {
+            problems.push("food_centroid has to include at least one food".into());
+        }
+        if self.hazard_forecast == Some(0) {
+            problems.push("hazard_forecast has to be at least one turn".into());
+        }
+        if self.lookahead > self.max_lookahead {
+            problems.push(format!(
+                "lookahead of {} exceeds the max_lookahead of {}",
+                self.lookahead, self.max_lookahead
+            ));
+        }
+        if let FallbackOrder::Fixed(dirs) = self.fallback {
+            for (i, dir) in dirs.iter().enumerate() {
+                if dirs[..i].contains(dir) {
+                    problems.push(format!("fallback repeats {dir:?}"));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Forgets the committed targets of a game that (re)starts.
+    pub fn on_start(&self, request: &GameRequest) {
+        self.committed
+            .lock()
+            .unwrap()
+            .retain(|(id, _), _| *id != request.game.id);
+    }
+
     pub async fn step(&self, game: &Game) -> MoveResponse {
-        let my = &game.snakes[0];
+        self.step_snake(game, 0).await
+    }
 
-        let mut food = Vec::new();
-        for y in 0..game.grid.height as i16 {
-            for x in 0..game.grid.width as i16 {
-                if game.grid[v2(x, y)].t == CellT::Food {
-                    food.push(v2(x, y));
+    /// Computes the move of the snake with the given index.
+    pub async fn step_snake(&self, game: &Game, snake: u8) -> MoveResponse {
+        self.step_game("", game, snake).await
+    }
+
+    /// Computes the move of the snake with the given index in the game with
+    /// the given id, which is used to commit to food targets.
+    ///
+    /// Takes the best of the [`Game::ranked_moves_to`] the targeted food,
+    /// choosing randomly between equally good moves.
+    pub async fn step_game(&self, id: &str, game: &Game, snake: u8) -> MoveResponse {
+        self.step_cancellable(id, game, snake, None, None).await
+    }
+
+    /// Like [`StarAgent::step_game`], but returns the fallback move as soon
+    /// as `cancel` is cancelled.
+    ///
+    /// With a `timeout` (in ms), the food searches on boards above the
+    /// maximum board size expand at most [`NODES_PER_MS`] nodes per ms,
+    /// which bounds their costs.
+    /// If the nearest food is not found within this budget, the agent
+    /// follows the partial path toward it.
+    pub async fn step_cancellable(
+        &self,
+        id: &str,
+        game: &Game,
+        snake: u8,
+        timeout: Option<u64>,
+        cancel: Option<&CancellationToken>,
+    ) -> MoveResponse {
+        let my = &game.snakes[snake as usize];
+        let cancelled = || cancel.is_some_and(CancellationToken::is_cancelled);
+        let fallback = || {
+            let dir = fallback_move_with(game, snake, self.fallback);
+            MoveResponse::rationale(dir, "cancelled, fallback move".into())
+        };
+
+        if cancelled() {
+            return fallback();
+        }
+
+        if self.aggressive {
+            if let Some((enemy, target)) = self.prey(game, snake) {
+                let ranked = self.ranked_moves(game, snake, Some(target));
+                if let Some(&(dir, score)) = ranked.first().filter(|(dir, _)| {
+                    !self.guard_hunt || Self::hunt_traps(game, snake, enemy, *dir)
+                }) {
+                    return MoveResponse::rationale(
+                        dir,
+                        format!("hunting snake {enemy} at {target}, score {score:.2}"),
+                    );
+                }
+            }
+            if let Some((enemy, food)) = self.denied_food(game, snake) {
+                let ranked = self.ranked_moves(game, snake, Some(food));
+                if let Some(&(dir, score)) = ranked.first() {
+                    return MoveResponse::rationale(
+                        dir,
+                        format!("denying food at {food} to snake {enemy}, score {score:.2}"),
+                    );
                 }
             }
         }
 
-        if let Some(target) = food
-            .iter()
-            .min_by(|&&a, &&b| {
-                let distance_a = ((a.x - my.head().x).pow(2) + (a.y - my.head().y).pow(2)) as f64;
-                let distance_b = ((b.x - my.head().x).pow(2) + (b.y - my.head().y).pow(2)) as f64;
-                distance_a
-                    .partial_cmp(&distance_b)
-                    .unwrap_or(Ordering::Equal)
-            })
-            .copied()
+        if cancelled() {
+            return fallback();
+        }
+
+        if game.turn < self.opening_turns {
+            let center = v2((game.grid.width / 2) as _, (game.grid.height / 2) as _);
+            let ranked = self.ranked_moves(game, snake, Some(center));
+            if let Some(&(dir, score)) = ranked.first() {
+                return MoveResponse::rationale(
+                    dir,
+                    format!("opening toward the center {center}, score {score:.2}"),
+                );
+            }
+        }
+
+        // Regular boards are always searched completely
+        let budget = timeout
+            .filter(|_| super::oversized(game))
+            .map(|t| t as usize * NODES_PER_MS);
+        let mut foods = game
+            .food_targets_budget(snake, self.heuristic, budget)
+            .take_while(|_| !cancelled())
+            .filter(|(target, path)| {
+                // Partial paths end before the food, so its space is unknown
+                !self.food_space
+                    || path.last() != Some(target)
+                    || game.space_after_path(snake, path) > my.body.len()
+            });
+        let (food, committed) = if let Some(k) = self.food_centroid {
+            let foods: Vec<_> = foods.take(k).collect();
+            let target = Self::centroid_target(foods.iter().map(|(food, _)| *food).collect());
+            (
+                foods.into_iter().find(|(food, _)| Some(*food) == target),
+                false,
+            )
+        } else if self.commit && !id.is_empty() {
+            self.commit_target(id, snake, foods.collect())
+        } else {
+            (foods.next(), false)
+        };
+        // If the search budget ran out, the path ends before the food and
+        // only shows the way toward it
+        let (target, approach) = match food {
+            Some((food, path)) if path.last() != Some(&food) => (None, Some((food, path))),
+            food => (food.map(|(food, _)| food), None),
+        };
+
+        if cancelled() {
+            return fallback();
+        }
+
+        let mut ranked = self.ranked_moves(game, snake, target);
+        // Prefer the moves that cannot lose in this turn
+        let safe = game.guaranteed_safe_moves(snake);
+        if ranked.iter().any(|(d, _)| safe.contains(d)) {
+            ranked.retain(|(d, _)| safe.contains(d));
+        }
+        // Larger lookaheads are reported by the validation
+        let lookahead = self.lookahead.min(self.max_lookahead);
+        if lookahead > 0 {
+            let surviving: Vec<(Direction, f64)> = ranked
+                .iter()
+                .copied()
+                .filter(|&(d, _)| {
+                    game.survives_replies_cancellable(snake, d, lookahead, &cancelled)
+                })
+                .collect();
+            if cancelled() {
+                return fallback();
+            }
+            // If every move loses, keep the best of them
+            if !surviving.is_empty() {
+                ranked = surviving;
+            }
+        }
+
+        // Without food to restore the health, leave the hazards quickly,
+        // unless that risks a head to head or a trap
+        let hazardous = |d: Direction| game.grid.is_hazardous(game.neighbor(my.head(), d));
+        let boxed =
+            target.is_none() && !ranked.is_empty() && game.valid_moves(snake).all(hazardous);
+        if boxed {
+            ranked.sort_by_cached_key(|&(d, _)| {
+                let p = game.neighbor(my.head(), d);
+                (
+                    game.head_to_head_risk(snake, p),
+                    game.flood_fill(p) < my.body.len(),
+                    game.hazard_exit_distance(p),
+                )
+            });
+        }
+        let Some(&(_, best)) = ranked.first() else {
+            let dir = fallback_move_with(game, snake, self.fallback);
+            return MoveResponse::rationale(dir, "no valid moves".into());
+        };
+        if let Some((food, path)) = approach {
+            let next = path.get(1).copied();
+            if let Some(&(dir, score)) = ranked
+                .iter()
+                .find(|&&(d, _)| safe.contains(&d) && Some(game.neighbor(my.head(), d)) == next)
+            {
+                return MoveResponse::rationale(
+                    dir,
+                    format!(
+                        "approaching food at {food} on a partial path of {} moves, score {score:.2}, health {}",
+                        path.len() - 1,
+                        my.health
+                    ),
+                );
+            }
+        }
+        let dir = self.rng.with(|rng| {
+            ranked
+                .iter()
+                .take_while(|(_, score)| *score == best)
+                .map(|(d, _)| *d)
+                .choose(rng)
+                .unwrap_or_else(|| fallback_move_with(game, snake, self.fallback))
+        });
+
+        let rationale = match target {
+            Some(target) => format!(
+                "seeking food at {target}{}, score {best:.2} of {} moves, health {}",
+                if self.food_centroid.is_some() {
+                    " (centroid)"
+                } else if committed {
+                    " (committed)"
+                } else {
+                    ""
+                },
+                ranked.len(),
+                my.health
+            ),
+            None => format!(
+                "{}, score {best:.2} of {} moves, health {}",
+                if boxed {
+                    "boxed into hazards"
+                } else {
+                    "no reachable food"
+                },
+                ranked.len(),
+                my.health
+            ),
+        };
+        MoveResponse::rationale(dir, rationale)
+    }
+
+    /// Returns the food that is nearest to the centroid of the `foods`.
+    fn centroid_target(foods: Vec<Vec2D>) -> Option<Vec2D> {
+        let n = foods.len() as f64;
+        let (x, y) = foods.iter().fold((0.0, 0.0), |(x, y), p| {
+            (x + p.x as f64 / n, y + p.y as f64 / n)
+        });
+        foods.into_iter().min_by(|a, b| {
+            let distance = |p: &Vec2D| (p.x as f64 - x).powi(2) + (p.y as f64 - y).powi(2);
+            distance(a).total_cmp(&distance(b))
+        })
+    }
+
+    /// Returns the committed target if it is still one of the `foods`
+    /// (nearest first) and there is no food that is closer by the
+    /// [`StarAgent::commit_margin`], otherwise the nearest food.
+    ///
+    /// Also returns the path to the target and if the committed target was
+    /// kept.
+    fn commit_target(
+        &self,
+        id: &str,
+        snake: u8,
+        foods: Vec<(Vec2D, Vec<Vec2D>)>,
+    ) -> (Option<(Vec2D, Vec<Vec2D>)>, bool) {
+        let mut committed = self.committed.lock().unwrap();
+        let key = (id.to_string(), snake);
+
+        let nearest = foods
+            .first()
+            .map(|(target, path)| (*target, path.len() - 1));
+        let target = match committed
+            .get(&key)
+            .and_then(|c| foods.iter().find(|(target, _)| target == c))
         {
-            if let Some(path) = game.grid.a_star(my.head(), target, &[0.0, 0.0, 0.0, 0.0]) {
-                if path.len() >= 2 {
-                    return MoveResponse::new(move_check(
-                        game,
-                        Direction::from(path[1] - path[0]),
-                        &mut None,
-                    ));
+            Some((target, path))
+                if nearest.is_some_and(|(_, n)| match self.commit_margin {
+                    Some(margin) => path.len() - 1 <= n + margin,
+                    None => 2 * n > path.len() - 1,
+                }) =>
+            {
+                (Some(*target), true)
+            }
+            _ => (nearest.map(|(target, _)| target), false),
+        };
+
+        match target {
+            (Some(target), _) => {
+                if committed.len() >= MAX_COMMITTED && !committed.contains_key(&key) {
+                    // Forget the targets of another game, which has likely ended
+                    if let Some((other, _)) = committed.keys().find(|(other, _)| other != id) {
+                        let other = other.clone();
+                        committed.retain(|(id, _), _| *id != other);
+                    }
                 }
+                committed.insert(key, target);
+            }
+            (None, _) => {
+                committed.remove(&key);
             }
         }
-        MoveResponse::new(random(game, &mut None))
+        let (target, kept) = target;
+        (
+            target.and_then(|target| foods.into_iter().find(|(food, _)| *food == target)),
+            kept,
+        )
     }
-}
 
-thread_local! {
-    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy())
-}
+    /// Ranks the moves toward the target (see [`Game::ranked_moves_to`]),
+    /// applying the [`StarAgent::hazard_risk_tolerance`],
+    /// [`StarAgent::hazard_forecast`] and [`StarAgent::food_bias`].
+    fn ranked_moves(&self, game: &Game, snake: u8, target: Option<Vec2D>) -> Vec<(Direction, f64)> {
+        let forecast = self
+            .hazard_forecast
+            .map(|turns| game.hazard_forecast(turns));
+        // Paths toward food end with eating anyway
+        let food_bias = target
+            .filter(|&t| game.grid[t].t != CellT::Food)
+            .map_or(0.0, |_| self.food_bias);
+        let Some(tolerance) = self.hazard_risk_tolerance else {
+            return game.ranked_moves_with(
+                snake,
+                target,
+                self.heuristic,
+                HAZARD_WEIGHT,
+                forecast.as_deref(),
+                food_bias,
+            );
+        };
+        let mut ranked = game.ranked_moves_with(
+            snake,
+            target,
+            self.heuristic,
+            0.0,
+            forecast.as_deref(),
+            food_bias,
+        );
+        if game.snakes[snake as usize].health < tolerance {
+            let head = game.snakes[snake as usize].head();
+            let hazardous = |d: Direction| game.grid.is_hazardous(game.neighbor(head, d));
+            if ranked.iter().any(|&(d, _)| !hazardous(d)) {
+                ranked.retain(|&(d, _)| !hazardous(d));
+            }
+        }
+        ranked
+    }
+
+    /// Returns if moving in `dir` traps the enemy in an area that is smaller
+    /// than its body, without risking a head to head with a snake that is
+    /// at least as long.
+    fn hunt_traps(game: &Game, snake: u8, enemy: u8, dir: Direction) -> bool {
+        let p = game.neighbor(game.snakes[snake as usize].head(), dir);
+        !game.head_to_head_risk(snake, p)
+            && game.area_without(enemy, p) < game.snakes[enemy as usize].body.len()
+    }
+
+    /// Returns an opponent at or below the [`StarAgent::deny_food`] health
+    /// with a single reachable food that this snake reaches first.
+    fn denied_food(&self, game: &Game, snake: u8) -> Option<(u8, Vec2D)> {
+        let threshold = self.deny_food?;
+        let index = |p: Vec2D| p.x as usize + p.y as usize * game.grid.width;
+        let foods: Vec<Vec2D> = (0..game.grid.height as i16)
+            .flat_map(|y| (0..game.grid.width as i16).map(move |x| v2(x, y)))
+            .filter(|&p| game.grid[p].t == CellT::Food)
+            .collect();
+        let mut mine = None;
 
-fn random(game: &Game, nots: &mut Option<Vec<Direction>>) -> Direction {
-    let mut moves = game.valid_moves(0).collect::<Vec<Direction>>();
-    if let Some(nots) = nots {
-        for not in nots {
-            moves.retain(|dir| *dir != *not);
+        for (i, enemy) in game.snakes.iter().enumerate() {
+            if i == snake as usize || !enemy.alive() || enemy.health > threshold {
+                continue;
+            }
+            let theirs = game.distance_map(enemy.head());
+            let mut reachable = foods.iter().filter(|&&p| theirs[index(p)] != UNREACHABLE);
+            let (Some(&food), None) = (reachable.next(), reachable.next()) else {
+                continue;
+            };
+            let mine: &Vec<u16> =
+                mine.get_or_insert_with(|| game.distance_map(game.snakes[snake as usize].head()));
+            if mine[index(food)] < theirs[index(food)] {
+                return Some((i as u8, food));
+            }
         }
+        None
     }
-    if moves.is_empty() {
-        return *game
-            .valid_moves(0)
-            .collect::<Vec<Direction>>()
-            .first()
-            .unwrap_or(&Direction::Up);
+
+    /// Returns the nearest shorter snake and its predicted head, if the
+    /// snake can reach it within a few moves.
+    fn prey(&self, game: &Game, snake: u8) -> Option<(u8, Vec2D)> {
+        let my = &game.snakes[snake as usize];
+        game.snakes
+            .iter()
+            .enumerate()
+            .filter(|&(i, s)| i != snake as usize && s.alive() && s.body.len() < my.body.len())
+            .filter_map(|(i, _)| {
+                let target = game.predicted_head(i as u8);
+                // The prediction has to be a move that does not kill the enemy
+                let heading = Direction::all()
+                    .into_iter()
+                    .find(|&d| game.neighbor(game.snakes[i].head(), d) == target)?;
+                if !game.move_is_valid(i as u8, heading) {
+                    return None;
+                }
+                let path = game.a_star(my.head(), target, &[0.0; 4], self.heuristic)?;
+                (path.len() - 1 <= HUNT_DISTANCE).then_some((path.len(), i as u8, target))
+            })
+            .min_by_key(|&(len, i, _)| (len, i))
+            .map(|(_, i, target)| (i, target))
     }
-    move_check(
-        game,
-        *RNG.with_borrow_mut(|rng| moves.iter().choose(rng).unwrap_or(&Direction::Up)),
-        nots,
-    )
 }
 
-fn move_check(game: &Game, r#move: Direction, nots: &mut Option<Vec<Direction>>) -> Direction {
-    let my = &game.snakes[0];
-    let future_pos = my.head().apply(r#move);
-    for snake in &game.snakes[1..] {
-        if snake.body.len() >= my.body.len()
-            && Direction::all()
-                .iter()
-                .any(|dir| snake.head().apply(*dir) == future_pos)
-        {
-            match nots {
-                Some(nots) => nots.push(r#move),
-                None => *nots = Some(vec![r#move]),
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn star_agent_snake_index() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            0 . . . .
+            ^ . . 1 o
+            ^ . . ^ ."#,
+        )
+        .unwrap();
+        info!("{game:?}");
+
+        let agent = StarAgent::default();
+        let step = agent.step_snake(&game, 1).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Right);
+        assert!(step.rationale.contains("seeking food at (4,1)"));
+
+        // The public entry point still controls snake 0
+        let step = agent.step(&game).await;
+        assert!(game.move_is_valid(0, step.r#move));
+    }
+
+    #[tokio::test]
+    async fn star_agent_food_space() {
+        use super::*;
+        logging();
+
+        // The nearest food is in a dead end
+        let game = Game::parse(
+            r#"
+            . . . . o
+            . . . . .
+            0 < . . .
+            o ^ < . ."#,
+        )
+        .unwrap();
+
+        let agent = StarAgent::default();
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food at (4,3)"));
+        assert_eq!(step.r#move, Direction::Up);
+
+        let agent = StarAgent {
+            food_space: false,
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        assert!(step.rationale.contains("seeking food at (0,0)"));
+    }
+
+    #[tokio::test]
+    async fn star_agent_aggressive() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . 1 < < .
+            . . 0 . . . o
+            . . ^ < < < ."#,
+        )
+        .unwrap();
+        info!("{game:?}");
+
+        let agent = StarAgent {
+            aggressive: true,
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Up);
+        assert!(step.rationale.contains("hunting snake 1 at (2,2)"));
+
+        // Passive agents go for the food
+        let step = StarAgent::default().step(&game).await;
+        assert_eq!(step.r#move, Direction::Right);
+
+        // Equally long snakes are not hunted
+        let tail = game.snakes[1].body[0];
+        game.snakes[1].body.push_front(tail);
+        game.snakes[1].body.push_front(tail);
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food"));
+    }
+
+    #[tokio::test]
+    async fn star_agent_deny_food() {
+        use super::*;
+        logging();
+
+        // The opponent can only reach the food at (4,1), as the head of
+        // snake 0 blocks the corridor
+        let mut game = Game::parse(
+            r#"
+            . o 0 . o . . 1
+            . > ^ > > > > ^"#,
+        )
+        .unwrap();
+        game.snakes[1].health = 10;
+
+        let agent = StarAgent {
+            food_space: false,
+            aggressive: true,
+            deny_food: Some(20),
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{:?} ({})", step.r#move, step.rationale);
+        assert_eq!(step.r#move, Direction::Right);
+        assert!(step
+            .rationale
+            .starts_with("denying food at (4,1) to snake 1"));
+
+        // Without denial, the agent eats the nearest food
+        let step = StarAgent {
+            deny_food: None,
+            ..agent.clone()
+        }
+        .step(&game)
+        .await;
+        assert_eq!(step.r#move, Direction::Left);
+
+        // Healthy opponents are not starved out
+        game.snakes[1].health = 50;
+        let step = agent.step(&game).await;
+        assert_eq!(step.r#move, Direction::Left);
+    }
+
+    #[tokio::test]
+    async fn star_agent_guard_hunt() {
+        use super::*;
+        logging();
+
+        let naive = StarAgent {
+            aggressive: true,
+            ..StarAgent::default()
+        };
+        let guarded = StarAgent {
+            guard_hunt: true,
+            ..naive.clone()
+        };
+
+        // Cutting off snake 1 at the wall traps it
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . . .
+            1 ^ . . .
+            ^ ^ . . .
+            ^ ^ . . ."#,
+        )
+        .unwrap();
+        info!("{game:?}");
+        for agent in [&naive, &guarded] {
+            let step = agent.step_snake(&game, 0).await;
+            info!("{}", step.rationale);
+            assert_eq!(step.r#move, Direction::Left);
+            assert!(step.rationale.contains("hunting snake 1 at (0,3)"));
+        }
+
+        // Snake 1 escapes through the open board
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . 1 < < .
+            . . 0 . . . o
+            . . ^ < < < ."#,
+        )
+        .unwrap();
+        let step = naive.step(&game).await;
+        assert!(step.rationale.contains("hunting snake 1"));
+        let step = guarded.step(&game).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food"));
+    }
+
+    #[tokio::test]
+    async fn star_agent_lookahead() {
+        use super::*;
+        logging();
+
+        // Snake 1 can close the corner with the food
+        let game = Game::parse(
+            r#"
+            . . . . .
+            v . . . .
+            v . . . .
+            0 . . . .
+            o . 1 < <"#,
+        )
+        .unwrap();
+        info!("{game:?}");
+
+        let step = StarAgent::default().step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Down);
+
+        let agent = StarAgent {
+            lookahead: 1,
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert_ne!(step.r#move, Direction::Down);
+        assert!(game.survives_replies(0, step.r#move, 1));
+        assert!(!game.survives_replies(0, Direction::Down, 1));
+    }
+
+    #[tokio::test]
+    async fn star_agent_food_centroid() {
+        use super::*;
+        logging();
+
+        // A single food on the left and a cluster on the upper right
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . o o
+            . . . . . o o
+            . . . . . . .
+            . o . 0 . . .
+            . . . ^ . . ."#,
+        )
+        .unwrap();
+
+        let step = StarAgent::default().step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Left);
+
+        let agent = StarAgent {
+            food_centroid: Some(5),
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert!(matches!(step.r#move, Direction::Up | Direction::Right));
+        assert!(step.rationale.contains("(5,3) (centroid)"));
+    }
+
+    #[tokio::test]
+    async fn star_agent_node_budget() {
+        use super::*;
+        logging();
+
+        // The only food is in the bottom right corner, right behind a long
+        // wall, so that the search explores most of the board first
+        // (the corridor behind the wall is too small for the food space)
+        let walled = |size: usize| {
+            let wall = size - 2;
+            let mut board = String::new();
+            for y in (0..size).rev() {
+                for x in 0..size {
+                    board.push(match (x, y) {
+                        (0, 1) => '0',
+                        (0, 0) => '^',
+                        (x, 0) if x == size - 1 => 'o',
+                        _ => '.',
+                    });
+                    board.push(' ');
+                }
+                board.push('\n');
+            }
+            let mut game = Game::parse(&board).unwrap();
+            for y in 0..size - 1 {
+                game.grid[v2(wall as _, y as _)].t = CellT::Wall;
+            }
+            game
+        };
+        let agent = StarAgent {
+            force: true,
+            food_space: false,
+            ..StarAgent::default()
+        };
+
+        // The default timeout minus the default latency
+        let timeout = Some(300);
+
+        // Regular boards are searched completely
+        let game = walled(19);
+        let step = agent.step_cancellable("", &game, 0, timeout, None).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.starts_with("seeking food at (18,0)"));
+
+        // Oversized boards are bounded by the timeout
+        let game = walled(31);
+        let step = agent.step_cancellable("", &game, 0, None, None).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.starts_with("seeking food at (30,0)"));
+
+        // Without finding the food within the budget, the agent follows the
+        // partial path toward it
+        let step = agent.step_cancellable("", &game, 0, timeout, None).await;
+        info!("{:?}: {}", step.r#move, step.rationale);
+        assert!(step.rationale.starts_with("approaching food at (30,0)"));
+        assert_eq!(step.r#move, Direction::Right);
+    }
+
+    #[tokio::test]
+    async fn star_agent_max_lookahead() {
+        use super::*;
+        use crate::agents::Agent;
+        use std::time::{Duration, Instant};
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . 1 < < . . 2 < < .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . 0 . . . . .
+            . . . . . ^ . . . . .
+            . . . . . ^ . . . . .
+            . . . . . . . . . . .
+            . . 3 < < . . . . . .
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+
+        // An absurd lookahead is capped by default and stays within the
+        // timeout of the move program against three opponents
+        let agent = StarAgent {
+            lookahead: u8::MAX,
+            ..StarAgent::default()
+        };
+        let timer = Instant::now();
+        let step = Agent::AStar(agent)
+            .step_game("game", 300, &game, None)
+            .await;
+        info!("{:?}: {}", timer.elapsed(), step.rationale);
+        assert!(timer.elapsed() < Duration::from_millis(300));
+
+        let capped = StarAgent {
+            lookahead: MAX_LOOKAHEAD,
+            ..StarAgent::default()
+        };
+        assert_eq!(capped.step(&game).await.r#move, step.r#move);
+        assert!(game.survives_replies(0, step.r#move, MAX_LOOKAHEAD));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn star_agent_cancelled_lookahead() {
+        use super::*;
+        use crate::agents::Agent;
+        use std::time::{Duration, Instant};
+        use tokio_util::sync::CancellationToken;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . 1 < < . . 2 < < .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . 0 . . . . .
+            . . . . . ^ . . . . .
+            . . . . . ^ . . . . .
+            . . . . . . . . . . .
+            . . 3 < < . . . . . .
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+
+        // Without cancellation, this lookahead runs far beyond any timeout
+        let agent = StarAgent {
+            lookahead: 8,
+            max_lookahead: 8,
+            ..StarAgent::default()
+        };
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            canceller.cancel();
+        });
+
+        let timer = Instant::now();
+        let step = Agent::AStar(agent)
+            .step_game("game", 300, &game, Some(&cancel))
+            .await;
+        info!("{:?}: {}", timer.elapsed(), step.rationale);
+        assert!(timer.elapsed() < Duration::from_secs(2));
+        assert!(step.rationale.starts_with("cancelled"));
+        assert!(game.move_is_valid(0, step.r#move));
+    }
+
+    #[tokio::test]
+    async fn star_agent_hazard_risk_tolerance() {
+        use super::*;
+        use crate::game::Snake;
+        logging();
+
+        // The hazard at (9,0) is the only gap in a wall of snake 1,
+        // the detour over the top is much longer than the hazard damage
+        let you = Snake::new([v2(6, 0), v2(7, 0), v2(8, 0)].into(), 100);
+        // The stacked tail does not move
+        let wall = Snake::new(
+            [1, 1].into_iter().chain(1..18).map(|y| v2(9, y)).collect(),
+            100,
+        );
+        let game = Game::new(0, 19, 19, vec![you, wall], &[v2(10, 0)], &[v2(9, 0)]);
+        info!("{game:?}");
+
+        let agent = StarAgent {
+            hazard_risk_tolerance: Some(30),
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Right);
+
+        // By default, moves into hazards are penalized
+        let step = StarAgent::default().step(&game).await;
+        assert_eq!(step.r#move, Direction::Up);
+
+        // At low health, the agent refuses the hazard
+        let mut low = game.clone();
+        low.snakes[0].health = 20;
+        let step = agent.step(&low).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Up);
+    }
+
+    #[tokio::test]
+    async fn star_agent_boxed_into_hazards() {
+        use super::*;
+        logging();
+
+        // Only the left column is safe, but the longer snake 1 might move
+        // into the nearest exit
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . 1 < < < . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        for y in 0..6 {
+            for x in 1..7 {
+                game.grid[v2(x, y)].hazard = true;
+            }
+        }
+        let step = StarAgent::default().step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Down);
+        assert!(step.rationale.starts_with("boxed into hazards"));
+
+        // Against a shorter snake, the nearest exit is safe
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . 1 < . . . .
+            . . 0 < < < .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        for y in 0..6 {
+            for x in 1..7 {
+                game.grid[v2(x, y)].hazard = true;
+            }
+        }
+        let step = StarAgent::default().step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Left);
+        assert!(step.rationale.starts_with("boxed into hazards"));
+    }
+
+    #[tokio::test]
+    async fn star_agent_hazard_forecast() {
+        use super::*;
+        logging();
+
+        // The two left columns are hazardous and the next shrink is in one
+        // turn, which might cover the column of the snake
+        let mut game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . o . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . 0 . . . . . . . .
+            . . ^ . . . . . . . ."#,
+        )
+        .unwrap();
+        for y in 0..11 {
+            for x in 0..2 {
+                game.grid[v2(x, y)].hazard = true;
             }
-            return random(game, nots);
         }
+        game.turn = 24;
+
+        let step = StarAgent::default().step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Up);
+
+        let agent = StarAgent {
+            hazard_forecast: Some(25),
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert_eq!(step.r#move, Direction::Right);
+    }
+
+    #[tokio::test]
+    async fn star_agent_opening() {
+        use super::*;
+        use crate::game::START_HEALTH;
+        logging();
+
+        let game = Game::standard_start(11, 11, 1, START_HEALTH);
+        let head = game.snakes[0].head();
+        assert_eq!(head, v2(1, 1));
+
+        let agent = StarAgent {
+            opening_turns: 3,
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert!(step
+            .rationale
+            .starts_with("opening toward the center (5,5)"));
+        let center = v2(5, 5);
+        assert!(
+            (game.neighbor(head, step.r#move) - center).manhattan() < (head - center).manhattan()
+        );
+
+        // After the opening, the agent goes for the food
+        let mut game = game;
+        game.turn = 3;
+        let step = agent.step(&game).await;
+        assert!(step.rationale.starts_with("seeking food"));
+    }
+
+    #[tokio::test]
+    async fn star_agent_food_bias() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . 0 . o . . .
+            . ^ . . . . ."#,
+        )
+        .unwrap();
+
+        let agent = StarAgent {
+            opening_turns: 3,
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{:?}: {}", step.r#move, step.rationale);
+        assert_eq!(step.r#move, Direction::Up);
+
+        // Both moves are on a shortest path to the center, but only the
+        // right one passes the food
+        let agent = StarAgent {
+            opening_turns: 3,
+            food_bias: 0.5,
+            ..StarAgent::default()
+        };
+        let step = agent.step(&game).await;
+        info!("{:?}: {}", step.r#move, step.rationale);
+        assert_eq!(step.r#move, Direction::Right);
+        assert!(step
+            .rationale
+            .starts_with("opening toward the center (3,3)"));
+
+        let agent = StarAgent {
+            food_bias: 1.5,
+            ..StarAgent::default()
+        };
+        assert_eq!(
+            agent.problems(),
+            vec!["food_bias of 1.5 is not between 0 and 1".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn star_agent_commit() {
+        use super::*;
+        use crate::grid::CellT;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . o . .
+            . . . . .
+            . . . . .
+            . . . . .
+            . . 0 . ."#,
+        )
+        .unwrap();
+        let agent = StarAgent {
+            commit: true,
+            ..StarAgent::default()
+        };
+        let step = agent.step_game("game", &game, 0).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.starts_with("seeking food at (2,4),"));
+        assert_eq!(step.r#move, Direction::Up);
+
+        // A marginally closer food appears
+        game.step(&[Direction::Up]);
+        game.grid[v2(4, 1)].t = CellT::Food;
+        info!("{game:?}");
+
+        let step = agent.step_game("game", &game, 0).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food at (2,4) (committed)"));
+        assert_eq!(step.r#move, Direction::Up);
+
+        // Other games, games without id and agents without commitment target
+        // the nearest food
+        let step = agent.step_game("other", &game, 0).await;
+        assert!(step.rationale.contains("seeking food at (4,1)"));
+        let step = agent.step(&game).await;
+        assert!(step.rationale.starts_with("seeking food at (4,1),"));
+        let step = StarAgent::default().step(&game).await;
+        assert!(step.rationale.contains("seeking food at (4,1)"));
+
+        // A much closer food is preferred
+        game.grid[v2(2, 2)].t = CellT::Food;
+        let step = agent.step_game("game", &game, 0).await;
+        assert!(step.rationale.starts_with("seeking food at (2,2),"));
+
+        // Too many games only forget the targets of one of them
+        for i in 0..MAX_COMMITTED {
+            let _ = agent.step_game(&format!("game {i}"), &game, 0).await;
+        }
+        assert_eq!(agent.committed.lock().unwrap().len(), MAX_COMMITTED);
+    }
+
+    #[tokio::test]
+    async fn star_agent_commit_margin() {
+        use super::*;
+        use crate::grid::CellT;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . o . .
+            . . . . .
+            . . . . .
+            . . . . .
+            . . . . .
+            . . . . .
+            . . 0 . ."#,
+        )
+        .unwrap();
+        let agent = StarAgent {
+            commit: true,
+            commit_margin: Some(3),
+            ..StarAgent::default()
+        };
+        let start = game.clone();
+        let step = agent.step_game("game", &game, 0).await;
+        assert!(step.rationale.starts_with("seeking food at (2,6),"));
+
+        // Closer by 3 moves, which is within the margin
+        game.step(&[Direction::Up]);
+        game.grid[v2(2, 3)].t = CellT::Food;
+        info!("{game:?}");
+
+        let step = agent.step_game("game", &game, 0).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food at (2,6)"));
+
+        // The default commitment switches at half the distance
+        let default = StarAgent {
+            commit: true,
+            ..StarAgent::default()
+        };
+        let step = default.step_game("game", &start, 0).await;
+        assert!(step.rationale.starts_with("seeking food at (2,6),"));
+        let step = default.step_game("game", &game, 0).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.starts_with("seeking food at (2,3),"));
+
+        // But keeps the target for a food at more than half the distance
+        let mut farther = game.clone();
+        farther.grid[v2(2, 3)].t = CellT::Free;
+        farther.grid[v2(2, 4)].t = CellT::Food;
+        let _ = default.step_game("other", &start, 0).await;
+        let step = default.step_game("other", &farther, 0).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food at (2,6) (committed)"));
+
+        // Closer by more than the margin
+        game.grid[v2(2, 3)].t = CellT::Free;
+        game.grid[v2(2, 2)].t = CellT::Food;
+        let step = agent.step_game("game", &game, 0).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food at (2,2)"));
     }
 
-    r#move
+    #[tokio::test]
+    async fn star_agent_survivable_food() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . o .
+            o . 0 . . . .
+            . . ^ . . . ."#,
+        )
+        .unwrap();
+        // The nearest food is behind a hazard the snake would starve in
+        for y in 0..3 {
+            game.grid[v2(1, y)].hazard = true;
+        }
+        game.snakes[0].health = 5;
+        info!("{game:?}");
+
+        let agent = StarAgent::default();
+        let step = agent.step(&game).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food at (5,2)"));
+        assert!(matches!(step.r#move, Direction::Up | Direction::Right));
+
+        // With enough health, the nearest food is preferred
+        game.snakes[0].health = 50;
+        let step = agent.step(&game).await;
+        assert!(step.rationale.contains("seeking food at (0,1)"));
+    }
 }
@@ -0,0 +1,79 @@
+use super::{Agent, StarAgent, SurvivorAgent};
+use crate::game::{Game, START_HEALTH};
+
+/// Rule that decides which agent of a [`BlendAgent`] moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum BlendRule {
+    /// While longer than all opponents.
+    #[default]
+    Longest,
+    /// While the health is above the given value.
+    HealthAbove(u8),
+}
+
+impl BlendRule {
+    /// Returns if the rule applies to the snake.
+    pub fn applies(self, game: &Game, snake: u8) -> bool {
+        let my = &game.snakes[snake as usize];
+        match self {
+            BlendRule::Longest => game
+                .snakes
+                .iter()
+                .enumerate()
+                .filter(|&(i, s)| i != snake as usize && s.alive())
+                .all(|(_, s)| s.body.len() < my.body.len()),
+            BlendRule::HealthAbove(health) => my.health > health,
+        }
+    }
+}
+
+/// Switches between two agents, for high-level policies like hunting while
+/// being the longest snake and surviving otherwise.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BlendAgent {
+    pub rule: BlendRule,
+    /// Agent that moves while the rule applies.
+    pub primary: Box<Agent>,
+    /// Agent that moves otherwise.
+    pub secondary: Box<Agent>,
+}
+
+impl Default for BlendAgent {
+    fn default() -> Self {
+        let mut hunter = StarAgent::default();
+        hunter.aggressive = true;
+        Self {
+            rule: BlendRule::Longest,
+            primary: Box::new(Agent::AStar(hunter)),
+            secondary: Box::new(Agent::Survivor(SurvivorAgent)),
+        }
+    }
+}
+
+impl BlendAgent {
+    /// Returns the problems of the rule and both agents.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let BlendRule::HealthAbove(health) = self.rule {
+            if health >= START_HEALTH {
+                problems.push(format!("rule HealthAbove({health}) never applies"));
+            }
+        }
+        for (name, agent) in [("primary", &self.primary), ("secondary", &self.secondary)] {
+            if let Err(e) = agent.validate() {
+                problems.extend(e.into_iter().map(|p| format!("{name}: {p}")));
+            }
+        }
+        problems
+    }
+
+    /// Returns the agent that moves the snake and if it is the primary one.
+    pub fn choose(&self, game: &Game, snake: u8) -> (&Agent, bool) {
+        if self.rule.applies(game, snake) {
+            (&self.primary, true)
+        } else {
+            (&self.secondary, false)
+        }
+    }
+}
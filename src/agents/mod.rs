@@ -1,46 +1,260 @@
+use std::fmt;
 use std::str::FromStr;
-use std::string::ToString;
+
+use log::{error, warn};
+use tokio_util::sync::CancellationToken;
 
 mod astar;
 pub use astar::*;
+mod blend;
+pub use blend::*;
 mod random;
 pub use random::*;
+mod survivor;
+pub use survivor::*;
 
-use crate::game::Game;
+use crate::game::{fallback_move, Game};
+use crate::profile;
+use crate::util::AgentRng;
 
-use super::env::{GameRequest, MoveResponse};
+use super::env::{Direction, GameRequest, MoveResponse};
 
 const MAX_BOARD_SIZE: usize = 19;
 
+/// Returns if the board is larger than the maximum board size.
+fn oversized(game: &Game) -> bool {
+    game.grid.width > MAX_BOARD_SIZE || game.grid.height > MAX_BOARD_SIZE
+}
+
+/// Reports the serialized game if the thread panics while this guard is
+/// alive, so that crashes of an agent can be reproduced.
+pub struct PanicGuard<'a> {
+    game: &'a Game,
+    report: fn(&str),
+}
+
+impl<'a> PanicGuard<'a> {
+    /// Logs the game at the error level on panics.
+    pub fn new(game: &'a Game) -> Self {
+        Self::with_report(game, |msg| error!("{msg}"))
+    }
+
+    /// Calls `report` with the message on panics.
+    pub fn with_report(game: &'a Game, report: fn(&str)) -> Self {
+        Self { game, report }
+    }
+}
+
+impl Drop for PanicGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            (self.report)(&format!(
+                "panic at turn {}: {}",
+                self.game.turn,
+                self.game.to_json()
+            ));
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
 pub enum Agent {
     AStar(StarAgent),
     Random(RandomAgent),
+    Survivor(SurvivorAgent),
+    Blend(BlendAgent),
 }
 
 impl Default for Agent {
     fn default() -> Self {
-        Self::AStar(StarAgent)
+        Self::AStar(StarAgent::default())
     }
 }
 
 impl Agent {
-    pub async fn step(&self, request: &GameRequest, latency: u64) -> MoveResponse {
+    /// Short names of the agents, see [`Agent::from_name`].
+    pub const NAMES: [&'static str; 4] = ["astar", "random", "survivor", "blend"];
+
+    /// Creates the default configuration of an agent from its short name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "astar" => Some(Self::AStar(StarAgent::default())),
+            "random" => Some(Self::Random(RandomAgent::default())),
+            "survivor" => Some(Self::Survivor(SurvivorAgent)),
+            "blend" => Some(Self::Blend(BlendAgent::default())),
+            _ => None,
+        }
+    }
+
+    /// Checks the config for problems, like values that are out of range.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let problems = match self {
+            Agent::AStar(agent) => agent.problems(),
+            Agent::Blend(agent) => agent.problems(),
+            Agent::Random(_) | Agent::Survivor(_) => Vec::new(),
+        };
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Prepares the agent for a new game, before its first move.
+    ///
+    /// Agents can use this to precompute data that depends on the board.
+    pub fn on_start(&self, request: &GameRequest) {
+        match self {
+            Agent::AStar(agent) => agent.on_start(request),
+            Agent::Blend(agent) => {
+                agent.primary.on_start(request);
+                agent.secondary.on_start(request);
+            }
+            Agent::Random(_) | Agent::Survivor(_) => {}
+        }
+    }
+
+    /// Uses a seeded random number generator for reproducible moves.
+    pub fn seeded(self, seed: u64) -> Self {
+        match self {
+            Agent::AStar(agent) => Agent::AStar(agent.seeded(seed)),
+            Agent::Random(agent) => Agent::Random(agent.seeded(seed)),
+            Agent::Survivor(agent) => Agent::Survivor(agent),
+            Agent::Blend(agent) => Agent::Blend(BlendAgent {
+                primary: Box::new(agent.primary.seeded(seed)),
+                secondary: Box::new(agent.secondary.seeded(seed.wrapping_add(1))),
+                ..agent
+            }),
+        }
+    }
+
+    /// Returns the random number generator of the agent, if it has one.
+    fn rng(&self) -> Option<&AgentRng> {
+        match self {
+            Agent::AStar(agent) => Some(agent.rng()),
+            Agent::Random(agent) => Some(agent.rng()),
+            Agent::Survivor(_) => None,
+            Agent::Blend(agent) => agent.primary.rng().or_else(|| agent.secondary.rng()),
+        }
+    }
+
+    /// Returns if the agent is forced to move on boards that are larger than
+    /// the maximum board size (see [`StarAgent::force`]).
+    pub fn forced(&self) -> bool {
+        match self {
+            Agent::AStar(agent) => agent.force,
+            Agent::Blend(agent) => agent.primary.forced() && agent.secondary.forced(),
+            Agent::Random(_) | Agent::Survivor(_) => false,
+        }
+    }
+
+    /// Computes the move for the request.
+    ///
+    /// If `cancel` is cancelled (e.g. by a newer request for the same game),
+    /// the agents return early with a fallback move.
+    pub async fn step(
+        &self,
+        request: &GameRequest,
+        latency: u64,
+        cancel: Option<&CancellationToken>,
+    ) -> MoveResponse {
         let game = Game::from_request(request);
-        let timeout = request.game.timeout.saturating_sub(latency);
+        let timeout = request.game.timeout_or_default().saturating_sub(latency);
 
-        self.step_internal(timeout, &game).await
+        self.step_game(&request.game.id, timeout, &game, cancel)
+            .await
     }
 
-    pub async fn step_internal(&self, _timeout: u64, game: &Game) -> MoveResponse {
-        if game.grid.width > MAX_BOARD_SIZE || game.grid.height > MAX_BOARD_SIZE {
-            return RandomAgent.step(game).await;
+    pub async fn step_internal(&self, timeout: u64, game: &Game) -> MoveResponse {
+        self.step_game("", timeout, game, None).await
+    }
+
+    /// Computes the moves of all snakes in the game with the given id, each
+    /// controlled by this agent.
+    ///
+    /// Dead snakes get the default move.
+    pub async fn step_all(&self, id: &str, timeout: u64, game: &Game) -> Vec<MoveResponse> {
+        let mut responses = Vec::with_capacity(game.snakes.len());
+        for snake in 0..game.snakes.len() {
+            responses.push(self.step_snake(id, timeout, game, snake as u8, None).await);
+        }
+        responses
+    }
+
+    /// Computes the move of snake 0 in the game with the given id.
+    pub async fn step_game(
+        &self,
+        id: &str,
+        timeout: u64,
+        game: &Game,
+        cancel: Option<&CancellationToken>,
+    ) -> MoveResponse {
+        self.step_snake(id, timeout, game, 0, cancel).await
+    }
+
+    /// Computes the move of the snake with the given index in the game with
+    /// the given id.
+    ///
+    /// Without time left (e.g. if the latency exceeds the game timeout), the
+    /// agents are skipped for a valid move that avoids head to heads.
+    pub async fn step_snake(
+        &self,
+        id: &str,
+        timeout: u64,
+        game: &Game,
+        snake: u8,
+        cancel: Option<&CancellationToken>,
+    ) -> MoveResponse {
+        profile::reset();
+        let _guard = PanicGuard::new(game);
+
+        if !game.snake_is_alive(snake) {
+            return MoveResponse::rationale(Direction::Up, "dead, default move".into());
+        }
+
+        if timeout == 0 {
+            warn!("the latency exceeds the timeout, moving without search");
+            let head = game.snakes[snake as usize].head();
+            let dir = game
+                .valid_moves(snake)
+                .find(|&d| !game.head_to_head_risk(snake, game.neighbor(head, d)))
+                .unwrap_or_else(|| fallback_move(game, snake));
+            return MoveResponse::rationale(dir, "no time left, safe move".into());
+        }
+
+        if oversized(game) && !self.forced() {
+            // Agents without generator are reproducible with the fallback move
+            return match self.rng() {
+                Some(rng) => MoveResponse::rationale(
+                    rng.with(|rng| RandomAgent::random_move(game, snake, rng)),
+                    "board too large, random move".into(),
+                ),
+                None => MoveResponse::rationale(
+                    fallback_move(game, snake),
+                    "board too large, fallback move".into(),
+                ),
+            };
         }
 
         match self {
-            Agent::AStar(agent) => agent.step(game).await,
-            Agent::Random(agent) => agent.step(game).await,
+            Agent::AStar(agent) => {
+                agent
+                    .step_cancellable(id, game, snake, Some(timeout), cancel)
+                    .await
+            }
+            Agent::Random(agent) => agent.step_snake(game, snake).await,
+            Agent::Survivor(agent) => agent.step_snake(game, snake).await,
+            Agent::Blend(agent) => {
+                let (sub, primary) = agent.choose(game, snake);
+                let mut response = Box::pin(sub.step_snake(id, timeout, game, snake, cancel)).await;
+                response.rationale = format!(
+                    "{} agent, {}",
+                    if primary { "primary" } else { "secondary" },
+                    response.rationale
+                );
+                response
+            }
         }
     }
 }
@@ -53,8 +267,403 @@ impl FromStr for Agent {
     }
 }
 
-impl ToString for Agent {
-    fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap_or_default()
+impl fmt::Display for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn agent_from_name() {
+        use super::*;
+        logging();
+
+        assert!(matches!(Agent::from_name("random"), Some(Agent::Random(_))));
+        assert!(matches!(Agent::from_name("AStar"), Some(Agent::AStar(_))));
+        assert!(Agent::from_name("minimax").is_none());
+        for name in Agent::NAMES {
+            assert!(Agent::from_name(name).is_some());
+        }
+    }
+
+    #[test]
+    fn agent_validate() {
+        use super::*;
+        logging();
+
+        for name in Agent::NAMES {
+            assert!(Agent::from_name(name).unwrap().validate().is_ok());
+        }
+
+        let agent: Agent = r#"{"AStar": {"hazard_risk_tolerance": 150, "food_centroid": 0}}"#
+            .parse()
+            .unwrap();
+        let problems = agent.validate().unwrap_err();
+        info!("{problems:?}");
+        assert_eq!(
+            problems,
+            [
+                "hazard_risk_tolerance of 150 is above the maximum health of 100",
+                "food_centroid has to include at least one food",
+            ]
+        );
+
+        let agent: Agent = r#"{"Blend": {
+            "rule": {"HealthAbove": 100},
+            "secondary": {"AStar": {"lookahead": 9, "fallback": {"Fixed": ["up", "up", "left", "down"]}}}
+        }}"#
+        .parse()
+        .unwrap();
+        let problems = agent.validate().unwrap_err();
+        info!("{problems:?}");
+        assert_eq!(
+            problems,
+            [
+                "rule HealthAbove(100) never applies",
+                "secondary: lookahead of 9 exceeds the max_lookahead of 2",
+                "secondary: fallback repeats Up",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn agent_dead_you() {
+        use super::*;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{
+                "game": {"id": "dead", "timeout": 500},
+                "turn": 10,
+                "board": {
+                    "height": 5,
+                    "width": 5,
+                    "food": [],
+                    "hazards": [],
+                    "snakes": [
+                        {"id": "other", "name": "other", "health": 90, "body": [{"x": 3, "y": 3}, {"x": 3, "y": 2}]}
+                    ]
+                },
+                "you": {"id": "you", "name": "you", "health": 0, "body": []}
+            }"#,
+        )
+        .unwrap();
+
+        for agent in [Agent::default(), Agent::Random(RandomAgent::default())] {
+            let response = agent.step(&request, 0, None).await;
+            info!("{agent}: {:?} ({})", response.r#move, response.rationale);
+            assert_eq!(response.r#move, Direction::Up);
+        }
+    }
+
+    #[tokio::test]
+    async fn agent_no_time_left() {
+        use super::*;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{
+                "game": {"id": "game", "timeout": 100},
+                "turn": 10,
+                "board": {
+                    "height": 5,
+                    "width": 5,
+                    "food": [],
+                    "hazards": [],
+                    "snakes": [
+                        {"id": "other", "name": "other", "health": 90, "body": [{"x": 0, "y": 3}, {"x": 0, "y": 4}, {"x": 1, "y": 4}]}
+                    ]
+                },
+                "you": {"id": "you", "name": "you", "health": 90, "body": [{"x": 0, "y": 1}, {"x": 0, "y": 0}, {"x": 1, "y": 0}]}
+            }"#,
+        )
+        .unwrap();
+
+        // Up might lose a head to head
+        for agent in [Agent::default(), Agent::Survivor(SurvivorAgent)] {
+            let response = agent.step(&request, 200, None).await;
+            info!("{agent}: {:?} ({})", response.r#move, response.rationale);
+            assert!(response.rationale.starts_with("no time left"));
+            assert_eq!(response.r#move, Direction::Right);
+        }
+    }
+
+    #[tokio::test]
+    async fn agent_missing_timeout() {
+        use super::*;
+        use crate::env::DEFAULT_TIMEOUT;
+        logging();
+
+        for game in [r#"{"id": "game"}"#, r#"{"id": "game", "timeout": 0}"#] {
+            let request: GameRequest = serde_json::from_str(&format!(
+                r#"{{
+                    "game": {game},
+                    "turn": 10,
+                    "board": {{"height": 5, "width": 5, "food": [], "hazards": [], "snakes": []}},
+                    "you": {{"id": "you", "name": "you", "health": 90, "body": [{{"x": 2, "y": 2}}]}}
+                }}"#
+            ))
+            .unwrap();
+            assert_eq!(request.game.timeout_or_default(), DEFAULT_TIMEOUT);
+
+            let response = Agent::default().step(&request, 200, None).await;
+            info!("{:?} ({})", response.r#move, response.rationale);
+            assert!(!response.rationale.starts_with("no time left"));
+        }
+    }
+
+    #[tokio::test]
+    async fn agent_forced_large_board() {
+        use super::*;
+        logging();
+
+        let game = Game::standard_start(22, 22, 2, 100);
+
+        let response = Agent::default().step_internal(200, &game).await;
+        info!("{:?} ({})", response.r#move, response.rationale);
+        assert!(response.rationale.starts_with("board too large"));
+
+        // Reproducible with a seed
+        let (a, b) = (Agent::default().seeded(0), Agent::default().seeded(0));
+        for _ in 0..10 {
+            let response = a.step_internal(200, &game).await;
+            assert_eq!(b.step_internal(200, &game).await.r#move, response.r#move);
+        }
+
+        let agent: Agent = r#"{"AStar": {"force": true}}"#.parse().unwrap();
+        let response = agent.step_internal(200, &game).await;
+        info!("{:?} ({})", response.r#move, response.rationale);
+        assert!(response.rationale.starts_with("seeking food"));
+    }
+
+    #[tokio::test]
+    async fn agent_on_start() {
+        use super::*;
+        use crate::env::v2;
+        use crate::grid::CellT;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{
+                "game": {"id": "game", "timeout": 500},
+                "turn": 0,
+                "board": {"height": 5, "width": 5, "food": [], "hazards": [], "snakes": []},
+                "you": {"id": "you", "name": "you", "health": 100, "body": [{"x": 2, "y": 0}]}
+            }"#,
+        )
+        .unwrap();
+
+        let agent: Agent = r#"{"AStar": {"commit": true}}"#.parse().unwrap();
+        let mut game = Game::parse(
+            r#"
+            . . o . .
+            . . . . .
+            . . . . .
+            . . . . .
+            . . 0 . ."#,
+        )
+        .unwrap();
+        // The commitment of a previous game with the same id persists
+        // across its moves
+        let _ = agent.step_game("game", 200, &game, None).await;
+        game.grid[v2(4, 1)].t = CellT::Food;
+        let step = agent.step_game("game", 200, &game, None).await;
+        assert!(step.rationale.contains("seeking food at (2,4)"));
+
+        agent.on_start(&request);
+
+        let step = agent.step_game("game", 200, &game, None).await;
+        info!("{}", step.rationale);
+        assert!(step.rationale.contains("seeking food at (4,1)"));
+    }
+
+    #[test]
+    fn agent_panic_guard() {
+        use super::*;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::sync::Mutex;
+        logging();
+
+        static REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let game = Game::parse(
+            r#"
+            . . o
+            0 . .
+            ^ . ."#,
+        )
+        .unwrap();
+
+        {
+            let _guard = PanicGuard::with_report(&game, |m| REPORTS.lock().unwrap().push(m.into()));
+        }
+        assert!(REPORTS.lock().unwrap().is_empty());
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let _guard = PanicGuard::with_report(&game, |m| REPORTS.lock().unwrap().push(m.into()));
+            panic!("agent failure");
+        }));
+        assert!(result.is_err());
+
+        let reports = REPORTS.lock().unwrap();
+        info!("{reports:?}");
+        assert_eq!(reports.len(), 1);
+        let json = reports[0].strip_prefix("panic at turn 0: ").unwrap();
+        let logged = Game::from_json(json).unwrap();
+        assert_eq!(logged.grid, game.grid);
+        assert_eq!(logged.snakes, game.snakes);
+    }
+
+    #[tokio::test]
+    async fn agent_seeded() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 0 . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        for agent in [Agent::default(), Agent::Random(RandomAgent::default())] {
+            let game = &game;
+            let moves = |agent: Agent| async move {
+                let mut moves = Vec::new();
+                for _ in 0..20 {
+                    moves.push(agent.step_internal(200, game).await.r#move);
+                }
+                moves
+            };
+            let a = moves(agent.clone().seeded(42)).await;
+            let b = moves(agent.clone().seeded(42)).await;
+            info!("{a:?}");
+            assert_eq!(a, b);
+        }
+    }
+
+    #[tokio::test]
+    async fn agent_step_all() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . . .
+            . ^ . 1 .
+            . ^ . ^ o
+            . . . ^ ."#,
+        )
+        .unwrap();
+        let agent = Agent::default().seeded(0);
+        let responses = agent.step_all("game", 200, &game).await;
+        for r in &responses {
+            info!("{:?} ({})", r.r#move, r.rationale);
+        }
+        assert_eq!(responses.len(), 2);
+
+        // Both snakes head for the food
+        let mut next = game.clone();
+        next.step(&responses.iter().map(|r| r.r#move).collect::<Vec<_>>());
+        assert_eq!(
+            next.snapshot(),
+            r#"Game turn 1 5x5 {
+  . . . . .
+  . > 0 . .
+  . ^ . > 1
+  . . . ^ o
+  . . . . .
+  Snakes: [(0: 99), (1: 99)]
+}
+"#
+        );
+    }
+
+    #[tokio::test]
+    async fn agent_blend() {
+        use super::*;
+        logging();
+
+        let agent = Agent::from_name("blend").unwrap();
+
+        let longer = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^
+            . . . 0 . . .
+            . . . ^ . . .
+            . . . ^ . . .
+            . . . ^ . . ."#,
+        )
+        .unwrap();
+        let response = agent.step_internal(200, &longer).await;
+        info!("{:?} ({})", response.r#move, response.rationale);
+        assert!(response.rationale.starts_with("primary agent"));
+
+        let shorter = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^
+            . . . 0 . . ^
+            . . . ^ . . ^
+            . . . . . . ^
+            . . . . . . ."#,
+        )
+        .unwrap();
+        let response = agent.step_internal(200, &shorter).await;
+        info!("{:?} ({})", response.r#move, response.rationale);
+        assert!(response.rationale.starts_with("secondary agent, surviving"));
+
+        let agent: Agent =
+            r#"{"Blend": {"rule": {"HealthAbove": 50}, "secondary": {"Random": {}}}}"#
+                .parse()
+                .unwrap();
+        let response = agent.step_internal(200, &shorter).await;
+        assert!(response.rationale.starts_with("primary agent"));
+    }
+
+    #[tokio::test]
+    async fn agent_cancelled() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . . . o
+            > > 0 . .
+            . . . . ."#,
+        )
+        .unwrap();
+
+        let cancel = CancellationToken::new();
+        let response = Agent::default()
+            .step_game("game", 200, &game, Some(&cancel))
+            .await;
+        info!("{:?} ({})", response.r#move, response.rationale);
+        assert!(!response.rationale.starts_with("cancelled"));
+
+        cancel.cancel();
+        let response = Agent::default()
+            .step_game("game", 200, &game, Some(&cancel))
+            .await;
+        info!("{:?} ({})", response.r#move, response.rationale);
+        assert!(response.rationale.starts_with("cancelled"));
+        assert!(game.move_is_valid(0, response.r#move));
     }
 }
@@ -1,19 +1,45 @@
-use std::cell::RefCell;
+use rand::seq::IteratorRandom;
+use rand::Rng;
 
 use crate::env::*;
-use crate::game::Game;
-use rand::{rngs::SmallRng, seq::IteratorRandom, SeedableRng};
+use crate::game::{fallback_move, Game};
+use crate::util::AgentRng;
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
-pub struct RandomAgent;
-
-thread_local! {
-    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy())
+pub struct RandomAgent {
+    #[serde(skip)]
+    rng: AgentRng,
 }
 
 impl RandomAgent {
+    /// Uses a seeded random number generator for reproducible moves.
+    pub fn seeded(self, seed: u64) -> Self {
+        Self {
+            rng: AgentRng::seeded(seed),
+        }
+    }
+
+    /// Generator of the random moves.
+    pub(crate) fn rng(&self) -> &AgentRng {
+        &self.rng
+    }
+
     pub async fn step(&self, game: &Game) -> MoveResponse {
-        let moves = game.valid_moves(0);
-        MoveResponse::new(RNG.with_borrow_mut(|rng| moves.choose(rng).unwrap_or(Direction::Up)))
+        self.step_snake(game, 0).await
+    }
+
+    /// Computes the move of the snake with the given index.
+    pub async fn step_snake(&self, game: &Game, snake: u8) -> MoveResponse {
+        MoveResponse::rationale(
+            self.rng.with(|rng| Self::random_move(game, snake, rng)),
+            "random move".into(),
+        )
+    }
+
+    /// Returns a random valid move of the snake.
+    pub fn random_move(game: &Game, snake: u8, rng: &mut impl Rng) -> Direction {
+        game.valid_moves(snake)
+            .choose(rng)
+            .unwrap_or_else(|| fallback_move(game, snake))
     }
 }
@@ -0,0 +1,152 @@
+use std::cmp::Reverse;
+
+use crate::env::*;
+use crate::eval::UNREACHABLE;
+use crate::game::{fallback_move, Game};
+
+/// Maximizes the survived turns by keeping the largest reachable area and
+/// avoiding risky head to heads.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct SurvivorAgent;
+
+impl SurvivorAgent {
+    pub async fn step(&self, game: &Game) -> MoveResponse {
+        self.step_snake(game, 0).await
+    }
+
+    /// Computes the move of the snake with the given index.
+    ///
+    /// Prefers safe moves, then moves that keep the own tail reachable,
+    /// then larger areas and then moves closer to the own tail.
+    /// Chasing the own tail allows stalling without trapping itself.
+    pub async fn step_snake(&self, game: &Game, snake: u8) -> MoveResponse {
+        let my = &game.snakes[snake as usize];
+        let tail = my.body[0];
+        let index = |p: Vec2D| p.x as usize + p.y as usize * game.grid.width;
+
+        let best = game
+            .valid_moves(snake)
+            .map(|d| {
+                let p = game.neighbor(my.head(), d);
+                let distances = game.distance_map(p);
+                let tail_distance = if p == tail {
+                    0
+                } else {
+                    Direction::all()
+                        .into_iter()
+                        .map(|d| game.neighbor(tail, d))
+                        .filter(|&n| game.grid.has(n))
+                        .map(|n| distances[index(n)].saturating_add(1))
+                        .min()
+                        .unwrap_or(UNREACHABLE)
+                };
+                let area = distances.iter().filter(|&&d| d != UNREACHABLE).count();
+                let safe = !game.head_to_head_risk(snake, p);
+                (
+                    (
+                        safe,
+                        tail_distance < UNREACHABLE,
+                        area,
+                        Reverse(tail_distance),
+                    ),
+                    d,
+                )
+            })
+            // Prefer the first of equally good moves
+            .max_by(|a, b| a.0.cmp(&b.0).then((b.1 as u8).cmp(&(a.1 as u8))));
+
+        match best {
+            Some(((safe, _, area, _), dir)) => MoveResponse::rationale(
+                dir,
+                format!(
+                    "surviving with area {area}{}",
+                    if safe { "" } else { ", risking head to head" }
+                ),
+            ),
+            None => MoveResponse::rationale(fallback_move(game, snake), "no valid moves".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn survivor_agent_outlasts_random() {
+        use super::*;
+        use crate::agents::{Agent, RandomAgent};
+        use crate::game::Outcome;
+        use crate::playout::Playout;
+        use rand::rngs::SmallRng;
+        use rand::SeedableRng;
+        logging();
+
+        // A closed board without food
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . 0 < <
+            . . . . ^
+            . . . . ^"#,
+        )
+        .unwrap();
+        game.snakes[0].health = 50;
+        let playout = Playout {
+            food_rate: 0.0,
+            repetitions: 0,
+            ..Playout::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        async fn survived(
+            playout: &Playout,
+            agent: Agent,
+            game: &Game,
+            rng: &mut SmallRng,
+        ) -> usize {
+            let mut game = game.clone();
+            match playout.play_game(&[agent], &mut game, rng).await {
+                Outcome::Solo(turns) => turns,
+                outcome => panic!("unexpected {outcome:?}"),
+            }
+        }
+
+        // Survives until it starves
+        let survivor = survived(&playout, Agent::Survivor(SurvivorAgent), &game, &mut rng).await;
+        assert_eq!(survivor, 50);
+
+        // Seeded random agents survive less than 60% of that on average
+        let mut random = 0;
+        for seed in 0..10 {
+            let agent = Agent::Random(RandomAgent::default()).seeded(seed);
+            random += survived(&playout, agent, &game, &mut rng).await;
+        }
+        info!("survivor: {survivor}, random: {}", random as f64 / 10.0);
+        assert!(random < 6 * survivor);
+
+        // The left move is a dead end, which only the random agents take
+        let game = Game::parse(
+            r#"
+            . . . . .
+            # . . . .
+            . 0 < < <
+            # . . . .
+            . . . . ."#,
+        )
+        .unwrap();
+        let step = SurvivorAgent.step(&game).await;
+        info!("{:?} ({})", step.r#move, step.rationale);
+        assert_ne!(step.r#move, Direction::Left);
+
+        let mut dead_ends = 0;
+        for seed in 0..10 {
+            let step = RandomAgent::default().seeded(seed).step(&game).await;
+            dead_ends += usize::from(step.r#move == Direction::Left);
+        }
+        assert!(dead_ends > 0);
+    }
+}
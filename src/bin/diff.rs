@@ -0,0 +1,52 @@
+use owo_colors::OwoColorize;
+
+use hadar::diff::Change;
+use hadar::env::GameRequest;
+use hadar::game::Game;
+use hadar::logging_verbose;
+
+use clap::Parser;
+
+#[derive(Parser)]
+#[clap(
+    version,
+    author,
+    about = "Print the differences between two game states."
+)]
+struct Opts {
+    /// JSON Game request.
+    #[clap(value_parser = parse_game)]
+    before: Game,
+    /// JSON Game request or game state (e.g. a prediction of the simulation).
+    #[clap(value_parser = parse_game)]
+    after: Game,
+    /// Verbosity of the logs (-v for debug, -vv for trace).
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+fn parse_game(s: &str) -> Result<Game, serde_json::Error> {
+    match serde_json::from_str::<GameRequest>(s) {
+        Ok(request) => Ok(Game::from_request(&request)),
+        Err(_) => Game::from_json(s),
+    }
+}
+
+fn main() {
+    let Opts {
+        before,
+        after,
+        verbose,
+    } = Opts::parse();
+    logging_verbose(verbose);
+
+    let diff = before.diff(&after);
+    for d in &diff {
+        match d.change() {
+            Change::Added => println!("{}", d.green()),
+            Change::Removed => println!("{}", d.red()),
+            Change::Changed => println!("{}", d.yellow()),
+        }
+    }
+    println!("Differences: {}", diff.len());
+}
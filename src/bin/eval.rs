@@ -0,0 +1,66 @@
+use log::info;
+
+use hadar::env::GameRequest;
+use hadar::eval::{Terms, Weights};
+use hadar::game::Game;
+use hadar::logging_verbose;
+
+use clap::Parser;
+
+#[derive(Parser)]
+#[clap(
+    version,
+    author,
+    about = "Print the evaluation terms after each valid move."
+)]
+struct Opts {
+    /// JSON Game request.
+    #[clap(value_parser = parse_request)]
+    request: GameRequest,
+    /// Weights of the evaluation.
+    #[clap(long, value_parser = parse_weights, default_value = "{}")]
+    weights: Weights,
+    /// Verbosity of the logs (-v for debug, -vv for trace).
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+fn parse_request(s: &str) -> Result<GameRequest, serde_json::Error> {
+    serde_json::from_str(s)
+}
+
+fn parse_weights(s: &str) -> Result<Weights, serde_json::Error> {
+    serde_json::from_str(s)
+}
+
+fn main() {
+    let Opts {
+        request,
+        weights,
+        verbose,
+    } = Opts::parse();
+    logging_verbose(verbose);
+
+    let game = Game::from_request(&request);
+    info!("{game:?}");
+    info!("{weights:?}");
+
+    print!("{:<6}", "move");
+    for name in Terms::NAMES {
+        print!(" {name:>12}");
+    }
+    println!(" {:>12}", "total");
+
+    for (dir, terms) in game.evaluate_moves(0, &weights) {
+        print!("{:<6}", format!("{dir:?}"));
+        match terms {
+            Some(terms) => {
+                for value in terms.weighted(&weights) {
+                    print!(" {value:>12.4}");
+                }
+                println!(" {:>12.4}", terms.total(&weights));
+            }
+            None => println!(" {:>12}", "dead"),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use clap::Parser;
+use log::info;
+
+use hadar::agents::{Agent, RandomAgent};
+use hadar::logging_verbose;
+use hadar::playout::Playout;
+
+#[derive(Parser)]
+#[clap(
+    version,
+    author,
+    about = "Play an agent against a baseline and report its win rate."
+)]
+struct Opts {
+    /// Configuration of the agent.
+    agent: Agent,
+    /// Configuration of the baseline (the default A* agent by default).
+    #[clap(long, default_value_t)]
+    baseline: Agent,
+    /// Play against the random agent instead of the baseline.
+    #[clap(long, conflicts_with = "baseline")]
+    random: bool,
+    /// Number of games that are played.
+    #[clap(short, long, default_value_t = 20)]
+    game_count: usize,
+    /// Time each snake has for a turn.
+    #[clap(long, default_value_t = 200)]
+    timeout: u64,
+    /// Board width.
+    #[clap(long, default_value_t = 11)]
+    width: usize,
+    /// Board height.
+    #[clap(long, default_value_t = 11)]
+    height: usize,
+    /// Seed of the agents and games.
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// Verbosity of the logs (-v for debug, -vv for trace).
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+#[tokio::main]
+async fn main() {
+    let Opts {
+        agent,
+        baseline,
+        random,
+        game_count,
+        timeout,
+        width,
+        height,
+        seed,
+        verbose,
+    } = Opts::parse();
+    logging_verbose(verbose);
+
+    let baseline = if random {
+        Agent::Random(RandomAgent::default())
+    } else {
+        baseline
+    };
+    info!("{agent} against {baseline}");
+
+    let playout = Playout {
+        timeout,
+        ..Playout::default()
+    };
+    let ladder = playout
+        .ladder(&agent, &baseline, game_count, width, height, seed)
+        .await;
+
+    let mut deaths: Vec<_> = ladder.deaths.iter().collect();
+    deaths.sort_by_key(|&(_, n)| std::cmp::Reverse(*n));
+    for (cause, n) in deaths {
+        println!(
+            "Died by {cause:?}: {n} ({:.0}%)",
+            100.0 * *n as f64 / ladder.games() as f64
+        );
+    }
+    let (low, high) = ladder.confidence_interval();
+    println!(
+        "Result: {}/{}/{} (wins/losses/draws), win rate {:.2} [{low:.2}, {high:.2}]",
+        ladder.wins,
+        ladder.losses,
+        ladder.draws,
+        ladder.win_rate()
+    );
+}
@@ -1,11 +1,13 @@
-use log::info;
+use log::{debug, error, info};
 
 use hadar::agents::*;
 use hadar::env::GameRequest;
 use hadar::game::*;
-use hadar::logging;
+use hadar::logging_verbose;
+use hadar::playout::Playout;
 
 use clap::Parser;
+use rand::prelude::*;
 
 #[derive(Parser)]
 #[clap(version, author, about = "Simulate a move for an agent.")]
@@ -13,33 +15,130 @@ struct Opts {
     /// Default configuration.
     #[clap(long, default_value_t)]
     config: Agent,
+    /// Default configuration of an agent by its short name (astar, random, survivor).
+    #[clap(long, value_parser = parse_agent, conflicts_with = "config")]
+    agent: Option<Agent>,
     /// JSON Game request.
-    #[clap(value_parser = parse_request)]
-    request: GameRequest,
+    #[clap(value_parser = parse_request, required_unless_present = "check_config")]
+    request: Option<GameRequest>,
+    /// Only validate the configuration and print it with all defaults,
+    /// exiting with an error on problems.
+    #[clap(long)]
+    check_config: bool,
     /// Time in ms that is subtracted from the game timeouts.
     #[clap(long, default_value_t = 200)]
     latency: usize,
+    /// Compute the moves of all snakes with the agent and show the next board.
+    #[clap(long)]
+    all: bool,
+    /// Play up to this number of turns against random opponents that seek
+    /// food when hungry and show every board and move.
+    #[clap(long, conflicts_with = "all")]
+    timeline: Option<usize>,
+    /// Seed of the opponent moves in the timeline.
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// Verbosity of the logs (-v for debug, -vv for trace).
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
 fn parse_request(s: &str) -> Result<GameRequest, serde_json::Error> {
     serde_json::from_str(s)
 }
 
+fn parse_agent(s: &str) -> Result<Agent, String> {
+    Agent::from_name(s).ok_or_else(|| format!("expected one of {:?}", Agent::NAMES))
+}
+
 #[tokio::main]
 async fn main() {
-    logging();
-
     let Opts {
         config,
+        agent,
         request,
+        check_config,
         latency,
+        all,
+        timeline,
+        seed,
+        verbose,
     } = Opts::parse();
+    logging_verbose(verbose);
+    let config = agent.unwrap_or(config);
+
+    if check_config {
+        if let Err(problems) = config.validate() {
+            for problem in problems {
+                error!("{problem}");
+            }
+            std::process::exit(1);
+        }
+        println!("{}", serde_json::to_string_pretty(&config).unwrap());
+        return;
+    }
+    let request = request.expect("the request is required without --check-config");
 
     let game = Game::from_request(&request);
     info!("{config:?}");
     info!("{game:?}");
+    debug!("{}", Game::legend());
+    let unreachable = game.unreachable_food(0);
+    if !unreachable.is_empty() {
+        debug!("Unreachable food: {unreachable:?}");
+    }
+
+    if all {
+        let game = Game::from_request_full(&request);
+        let timeout = request
+            .game
+            .timeout_or_default()
+            .saturating_sub(latency as _);
+        let responses = config.step_all(&request.game.id, timeout, &game).await;
+        for (i, step) in responses.iter().enumerate() {
+            info!(
+                "Step {i} [{}]: {:?} ({})",
+                game.fingerprint(),
+                step.r#move,
+                step.rationale
+            );
+        }
+        let mut next = game;
+        next.step(&responses.iter().map(|r| r.r#move).collect::<Vec<_>>());
+        info!("Next: {next:?}");
+        return;
+    }
+
+    if let Some(max_turns) = timeline {
+        let playout = Playout {
+            timeout: request
+                .game
+                .timeout_or_default()
+                .saturating_sub(latency as _),
+            ..Playout::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let (steps, end) = playout.timeline(&config, &game, max_turns, &mut rng).await;
+        for step in steps {
+            info!("{:?}", step.game);
+            info!(
+                "Turn {}: {:?} ({})",
+                step.game.turn, step.r#move, step.rationale
+            );
+        }
+        info!("End: {end:?}");
+        info!("Outcome: {:?}", end.outcome());
+        return;
+    }
 
-    let step = config.step(&request, latency as _).await;
+    let step = config.step(&request, latency as _, None).await;
 
-    info!("Step: {step:?}");
+    info!(
+        "Step [{}]: {:?} ({})",
+        game.fingerprint(),
+        step.r#move,
+        step.rationale
+    );
+    #[cfg(feature = "profiling")]
+    info!("Profile: {:?}", hadar::profile::counters());
 }
@@ -1,26 +1,13 @@
-use std::convert::Infallible;
+use std::fs::OpenOptions;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
 
-use hadar::env::{GameRequest, IndexResponse, API_VERSION};
-use hadar::{agents::*, logging};
-use log::{info, warn};
+use hadar::server::{routes, State};
+use hadar::{agents::*, logging_verbose};
 
 use clap::Parser;
-use warp::Filter;
-
-pub const PACKAGE_VERSION: &str = env!("CARGO_PKG_VERSION");
-
-/// Runtime server configuration.
-struct State {
-    latency: u64,
-    color: String,
-    head: String,
-    tail: String,
-    author: String,
-    config: Agent,
-}
+use log::warn;
 
 /// High performant rust snake.
 #[derive(Debug, Parser)]
@@ -50,12 +37,16 @@ struct Opt {
     /// Default configuration.
     #[clap(long, default_value_t)]
     config: Agent,
+    /// Append every served request as JSON line to this file.
+    #[clap(long)]
+    record: Option<PathBuf>,
+    /// Verbosity of the logs (-v for debug, -vv for trace).
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
 #[tokio::main]
 async fn main() {
-    logging();
-
     let Opt {
         host,
         latency,
@@ -64,71 +55,27 @@ async fn main() {
         tail,
         author,
         config,
+        record,
+        verbose,
     } = Opt::parse();
-
-    let state = Arc::new(State {
-        latency,
-        color,
-        head,
-        tail,
-        author,
-        config,
-    });
-
-    let index = warp::get()
-        .and(warp::path::end())
-        .and(with_state(state.clone()))
-        .map(|state: Arc<State>| {
-            warn!("index");
-            warp::reply::json(&IndexResponse::new(
-                API_VERSION,
-                &state.author,
-                &state.color,
-                &state.head,
-                &state.tail,
-                PACKAGE_VERSION,
-            ))
-        });
-
-    let start = warp::path("start")
-        .and(warp::post())
-        .and(warp::body::json::<GameRequest>())
-        .map(|request: GameRequest| {
-            warn!("start {request}");
-            warp::reply()
-        });
-
-    let r#move = warp::path("move")
-        .and(warp::post())
-        .and(warp::body::json::<GameRequest>())
-        .and(with_state(state.clone()))
-        .and_then(step);
-
-    let end = warp::path("end")
-        .and(warp::post())
-        .and(warp::body::json::<GameRequest>())
-        .map(|request: GameRequest| {
-            warn!("end {request}");
-            warp::reply()
-        });
-
-    warp::serve(index.or(start).or(r#move).or(end))
-        .run(host)
-        .await;
-}
-
-fn with_state(
-    config: Arc<State>,
-) -> impl Filter<Extract = (Arc<State>,), Error = std::convert::Infallible> + Clone {
-    warp::any().map(move || config.clone())
-}
-
-async fn step(request: GameRequest, state: Arc<State>) -> Result<impl warp::Reply, Infallible> {
-    warn!("move {request}");
-
-    let timer = Instant::now();
-    let next_move = state.config.step(&request, state.latency).await;
-    info!("response time {:?}ms", timer.elapsed().as_millis());
-
-    Ok(warp::reply::json(&next_move))
+    logging_verbose(verbose);
+
+    if let Err(problems) = config.validate() {
+        for problem in problems {
+            warn!("{problem}");
+        }
+    }
+
+    let mut state = State::new(latency, color, head, tail, author, config);
+    if let Some(path) = record {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap_or_else(|e| panic!("could not open {path:?}: {e}"));
+        state = state.with_record(Box::new(file));
+    }
+    let state = Arc::new(state);
+
+    warp::serve(routes(state)).run(host).await;
 }
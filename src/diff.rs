@@ -0,0 +1,260 @@
+//! Structured differences between game states.
+
+use std::fmt;
+
+use crate::env::Vec2D;
+use crate::game::{Game, Snake};
+use crate::grid::{Cell, CellT};
+
+/// A single difference between two game states, see [`Game::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// The boards have different sizes, so their cells are not compared.
+    Size {
+        before: (usize, usize),
+        after: (usize, usize),
+    },
+    Turn {
+        before: usize,
+        after: usize,
+    },
+    Cell {
+        p: Vec2D,
+        before: Cell,
+        after: Cell,
+    },
+    /// Missing snakes have an empty body.
+    Body {
+        snake: u8,
+        before: Vec<Vec2D>,
+        after: Vec<Vec2D>,
+    },
+    Health {
+        snake: u8,
+        before: u8,
+        after: u8,
+    },
+}
+
+/// Kind of a [`Difference`], e.g. for coloring it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Something appeared on an empty cell or a new snake.
+    Added,
+    /// Something disappeared from a cell or a snake died.
+    Removed,
+    Changed,
+}
+
+impl Difference {
+    pub fn change(&self) -> Change {
+        const EMPTY: Cell = Cell::new(CellT::Free, false);
+        match self {
+            Difference::Cell { before, .. } if *before == EMPTY => Change::Added,
+            Difference::Cell { after, .. } if *after == EMPTY => Change::Removed,
+            Difference::Body { before, .. } if before.is_empty() => Change::Added,
+            Difference::Body { after, .. } if after.is_empty() => Change::Removed,
+            _ => Change::Changed,
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn cell(f: &mut fmt::Formatter<'_>, cell: Cell) -> fmt::Result {
+            match cell.t {
+                CellT::Free => write!(f, "free")?,
+                CellT::Food => write!(f, "food")?,
+                CellT::Owned => write!(f, "owned")?,
+                CellT::Wall => write!(f, "wall")?,
+            }
+            if cell.hazard {
+                write!(f, " (hazard)")?;
+            }
+            Ok(())
+        }
+        fn body(f: &mut fmt::Formatter<'_>, body: &[Vec2D]) -> fmt::Result {
+            write!(f, "[")?;
+            for (i, p) in body.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{p}")?;
+            }
+            write!(f, "]")
+        }
+
+        match self {
+            Difference::Size { before, after } => write!(
+                f,
+                "size: {}x{} -> {}x{}",
+                before.0, before.1, after.0, after.1
+            ),
+            Difference::Turn { before, after } => write!(f, "turn: {before} -> {after}"),
+            Difference::Cell { p, before, after } => {
+                write!(f, "cell {p}: ")?;
+                cell(f, *before)?;
+                write!(f, " -> ")?;
+                cell(f, *after)
+            }
+            Difference::Body {
+                snake,
+                before,
+                after,
+            } => {
+                write!(f, "snake {snake} body: ")?;
+                body(f, before)?;
+                write!(f, " -> ")?;
+                body(f, after)
+            }
+            Difference::Health {
+                snake,
+                before,
+                after,
+            } => write!(f, "snake {snake} health: {before} -> {after}"),
+        }
+    }
+}
+
+impl Game {
+    /// Returns the differences between this and the `other` game state,
+    /// ordered by turn, cells (bottom to top) and snakes.
+    ///
+    /// This is useful to compare the simulation with the game engine.
+    pub fn diff(&self, other: &Game) -> Vec<Difference> {
+        let mut diff = Vec::new();
+
+        if self.turn != other.turn {
+            diff.push(Difference::Turn {
+                before: self.turn,
+                after: other.turn,
+            });
+        }
+
+        let (size, other_size) = (
+            (self.grid.width, self.grid.height),
+            (other.grid.width, other.grid.height),
+        );
+        if size != other_size {
+            diff.push(Difference::Size {
+                before: size,
+                after: other_size,
+            });
+        } else {
+            for y in 0..self.grid.height {
+                for x in 0..self.grid.width {
+                    let p = Vec2D::new(x as _, y as _);
+                    let (before, after) = (self.grid[p], other.grid[p]);
+                    if before != after {
+                        diff.push(Difference::Cell { p, before, after });
+                    }
+                }
+            }
+        }
+
+        for snake in 0..self.snakes.len().max(other.snakes.len()) {
+            let before = self.snakes.get(snake);
+            let after = other.snakes.get(snake);
+            let body =
+                |s: Option<&Snake>| s.map_or_else(Vec::new, |s| s.body.iter().copied().collect());
+            let (before_body, after_body) = (body(before), body(after));
+            if before_body != after_body {
+                diff.push(Difference::Body {
+                    snake: snake as _,
+                    before: before_body,
+                    after: after_body,
+                });
+            }
+            let health = |s: Option<&Snake>| s.map_or(0, |s| s.health);
+            if health(before) != health(after) {
+                diff.push(Difference::Health {
+                    snake: snake as _,
+                    before: health(before),
+                    after: health(after),
+                });
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn diff_one_cell() {
+        use super::*;
+        logging();
+
+        let before = Game::parse(
+            r#"
+            . . .
+            . o .
+            0 . ."#,
+        )
+        .unwrap();
+        assert_eq!(before.diff(&before), Vec::new());
+
+        let after = Game::parse(
+            r#"
+            . . o
+            . o .
+            0 . ."#,
+        )
+        .unwrap();
+        let diff = before.diff(&after);
+        for d in &diff {
+            info!("{d}");
+        }
+        assert_eq!(diff.len(), 1);
+        assert!(matches!(diff[0], Difference::Cell { p, .. } if p == Vec2D::new(2, 2)));
+        assert_eq!(diff[0].change(), Change::Added);
+        assert_eq!(diff[0].to_string(), "cell (2,2): free -> food");
+
+        let diff = after.diff(&before);
+        assert_eq!(diff[0].change(), Change::Removed);
+    }
+
+    #[test]
+    fn diff_snakes() {
+        use super::*;
+        use crate::env::Direction;
+        logging();
+
+        let before = Game::parse(
+            r#"
+            . . .
+            0 . .
+            ^ . ."#,
+        )
+        .unwrap();
+        let mut after = before.clone();
+        after.step(&[Direction::Right]);
+
+        let diff = before.diff(&after);
+        for d in &diff {
+            info!("{d}");
+        }
+        assert!(matches!(
+            diff[0],
+            Difference::Turn {
+                before: 0,
+                after: 1
+            }
+        ));
+        assert!(diff.iter().any(|d| matches!(
+            d,
+            Difference::Health {
+                snake: 0,
+                before: 100,
+                after: 99
+            }
+        )));
+        assert!(diff
+            .iter()
+            .any(|d| matches!(d, Difference::Body { snake: 0, .. })));
+    }
+}
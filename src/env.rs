@@ -4,6 +4,7 @@
 /// requests.
 ///
 /// See: https://docs.battlesnake.com/api
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Debug};
 use std::mem::size_of;
@@ -13,6 +14,9 @@ pub const API_VERSION: &str = "1";
 
 pub const HAZARD_DAMAGE: u8 = 15;
 
+/// Timeout in ms for requests without a (valid) timeout.
+pub const DEFAULT_TIMEOUT: u64 = 500;
+
 /// Position in the a 2D grid.
 #[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Vec2D {
@@ -45,6 +49,33 @@ impl Vec2D {
     pub fn within(self, width: usize, height: usize) -> bool {
         self.x >= 0 && self.x < width as _ && self.y >= 0 && self.y < height as _
     }
+
+    /// Converts this bottom-left based position into top-left coordinates,
+    /// where y increases downward.
+    pub fn to_top_left(self, height: usize) -> Vec2D {
+        Vec2D::new(self.x, height as i16 - 1 - self.y)
+    }
+
+    /// Converts top-left coordinates into a bottom-left based position.
+    pub fn from_top_left(p: Vec2D, height: usize) -> Vec2D {
+        Vec2D::new(p.x, height as i16 - 1 - p.y)
+    }
+}
+
+/// Origin of the coordinate system.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Y increases upward, as used by battlesnake.
+    #[default]
+    BottomLeft,
+    /// Y increases downward, as used by many image and grid tools.
+    TopLeft,
+}
+
+impl fmt::Display for Vec2D {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
 }
 
 impl From<(i16, i16)> for Vec2D {
@@ -106,7 +137,7 @@ impl Neg for Vec2D {
 /// The Direction is returned as part of a `MoveResponse`.
 ///
 /// The Y-Axis is positive in the up direction, and X-Axis is positive to the right.
-#[derive(Serialize, Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 #[repr(u8)]
 pub enum Direction {
@@ -135,6 +166,44 @@ impl Direction {
             Self::Left => Self::Right,
         }
     }
+
+    /// Returns the direction after turning left (counterclockwise).
+    pub fn turn_left(&self) -> Self {
+        Self::from((*self as u8 + 3) % 4)
+    }
+
+    /// Returns the direction after turning right (clockwise).
+    pub fn turn_right(&self) -> Self {
+        Self::from((*self as u8 + 1) % 4)
+    }
+
+    /// Returns the direction when going straight, which is this heading.
+    pub fn straight(&self) -> Self {
+        *self
+    }
+
+    /// Returns the direction mirrored at the vertical axis (swaps left and right).
+    pub fn reflect_x(&self) -> Self {
+        match self {
+            Self::Right => Self::Left,
+            Self::Left => Self::Right,
+            d => *d,
+        }
+    }
+
+    /// Returns the direction mirrored at the horizontal axis (swaps up and down).
+    pub fn reflect_y(&self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+            d => *d,
+        }
+    }
+
+    /// Returns the direction rotated clockwise by 90 degrees.
+    pub fn rotate90(&self) -> Self {
+        self.turn_right()
+    }
 }
 
 impl From<Vec2D> for Direction {
@@ -172,12 +241,29 @@ pub struct GameData {
     #[serde(default)]
     pub ruleset: Ruleset,
     /// How much time your snake has to respond to requests for this Game in milliseconds.
+    #[serde(default)]
     pub timeout: u64,
     /// The source of this game. (tournament, league, arena, challenge, custom)
     #[serde(default)]
     pub source: String,
 }
 
+impl GameData {
+    /// Returns the timeout, or the [`DEFAULT_TIMEOUT`] if the request has
+    /// none (or zero), which would leave no time for the agents.
+    pub fn timeout_or_default(&self) -> u64 {
+        if self.timeout == 0 {
+            warn!(
+                "missing timeout in game {}, using {DEFAULT_TIMEOUT}ms",
+                self.id
+            );
+            DEFAULT_TIMEOUT
+        } else {
+            self.timeout
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Ruleset {
     pub name: String,
@@ -195,6 +281,32 @@ pub struct Battlesnake {
     pub body: Vec<Vec2D>,
     #[serde(default)]
     pub shout: String,
+    #[serde(default)]
+    pub customizations: Customizations,
+}
+
+/// Display customizations of a snake.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Customizations {
+    /// Hex color code like `#888888`.
+    #[serde(default)]
+    pub color: String,
+    #[serde(default)]
+    pub head: String,
+    #[serde(default)]
+    pub tail: String,
+}
+
+impl Customizations {
+    /// Returns the rgb values of the hex color code if it is valid.
+    pub fn rgb(&self) -> Option<[u8; 3]> {
+        let hex = self.color.strip_prefix('#')?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some([channel(0)?, channel(2)?, channel(4)?])
+    }
 }
 
 impl PartialEq for Battlesnake {
@@ -203,6 +315,24 @@ impl PartialEq for Battlesnake {
     }
 }
 
+/// A rectangle of cells with the bottom left cell at `x` and `y`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i16,
+    pub y: i16,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// Returns all cells of the rectangle, row by row from the bottom.
+    pub fn cells(self) -> impl Iterator<Item = Vec2D> {
+        (0..self.height as i16).flat_map(move |y| {
+            (0..self.width as i16).map(move |x| Vec2D::new(self.x + x, self.y + y))
+        })
+    }
+}
+
 /// The game board is represented by a standard 2D grid, oriented with (0,0) in the bottom left.
 /// The Y-Axis is positive in the up direction, and X-Axis is positive to the right.
 ///
@@ -224,6 +354,17 @@ pub struct Board {
     /// Array of coordinates representing hazardous locations on the game board.
     /// These will only appear in some game modes.
     pub hazards: Vec<Vec2D>,
+    /// Rectangular hazard zones, in addition to the `hazards` cells.
+    ///
+    /// This is not part of the official API, but shortens requests of maps
+    /// with large hazard zones (e.g. for tests of royale games).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hazard_zones: Vec<Rect>,
+    /// Permanent obstacles of the map, like the islands of some maps.
+    ///
+    /// This is also an extension to the official API.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub walls: Vec<Vec2D>,
     /// Array of [Battlesnake] Objects representing all Battlesnakes remaining on
     /// the game board (including yourself if you haven't been eliminated).
     pub snakes: Vec<Battlesnake>,
@@ -289,6 +430,9 @@ impl<'a> IndexResponse<'a> {
 pub struct MoveResponse {
     pub r#move: Direction,
     pub shout: String,
+    /// Short explanation of the decision for the logs, which is not sent.
+    #[serde(skip)]
+    pub rationale: String,
 }
 
 impl MoveResponse {
@@ -296,9 +440,63 @@ impl MoveResponse {
         Self {
             r#move,
             shout: String::new(),
+            rationale: String::new(),
         }
     }
     pub fn shout(r#move: Direction, shout: String) -> Self {
-        Self { r#move, shout }
+        Self {
+            r#move,
+            shout,
+            rationale: String::new(),
+        }
+    }
+    pub fn rationale(r#move: Direction, rationale: String) -> Self {
+        Self {
+            r#move,
+            shout: String::new(),
+            rationale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::logging;
+
+    #[test]
+    fn vec2d_top_left() {
+        use super::*;
+        logging();
+
+        assert_eq!(v2(1, 0).to_top_left(5), v2(1, 4));
+        assert_eq!(v2(3, 4).to_top_left(5), v2(3, 0));
+        assert_eq!(Vec2D::from_top_left(v2(1, 4), 5), v2(1, 0));
+        for y in 0..5 {
+            for x in 0..3 {
+                let p = v2(x, y);
+                assert!(p.to_top_left(5).within(3, 5));
+                assert_eq!(Vec2D::from_top_left(p.to_top_left(5), 5), p);
+                assert_eq!(Vec2D::from_top_left(p, 5).to_top_left(5), p);
+            }
+        }
+    }
+
+    #[test]
+    fn direction_turns() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        assert_eq!(Up.turn_right(), Right);
+        assert_eq!(Right.turn_right(), Down);
+        assert_eq!(Down.turn_right(), Left);
+        assert_eq!(Left.turn_right(), Up);
+
+        for d in Direction::all() {
+            assert_eq!(d.turn_left().turn_right(), d);
+            assert_eq!(d.turn_left().turn_left(), d.invert());
+            assert_eq!(d.turn_right().turn_right().turn_right(), d.turn_left());
+            assert_eq!(d.straight(), d);
+        }
     }
 }
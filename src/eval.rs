@@ -0,0 +1,1766 @@
+//! Heuristics that rate positions and moves.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+
+use crate::env::{v2, Direction, Vec2D, HAZARD_DAMAGE};
+use crate::game::{fallback_move, Game, Snake};
+use crate::grid::{AStarOptions, CellT, Grid, Heuristic};
+use crate::profile;
+use crate::util::Fnv1a;
+
+/// Penalty for moves that might lose a head to head.
+const SAFETY_WEIGHT: f64 = 1000.0;
+/// Penalty for moves into areas that are smaller than the snake.
+const TRAP_WEIGHT: f64 = 100.0;
+/// Reward for moves that are close to the targeted food.
+const FOOD_WEIGHT: f64 = 10.0;
+/// Reward for moves into hazards, see [`Game::ranked_moves_to`].
+pub const HAZARD_WEIGHT: f64 = -1.0;
+/// Reward for the relative size of the reachable area.
+const SPACE_WEIGHT: f64 = 1.0;
+/// Reward for the share of open exits, see [`Game::exit_count`].
+const EXIT_WEIGHT: f64 = 0.5;
+/// Number of cells that are two moves away from a cell.
+const MAX_EXITS: usize = 8;
+/// Maximum number of valid moves within two turns, see [`Game::mobility`].
+const MAX_MOBILITY: usize = 3 + 3 * 3;
+
+/// Weights of the position evaluation, see [`Game::evaluate_with`].
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    /// Share of the cells the snake reaches first.
+    pub area: f64,
+    /// Health of the snake relative to the maximum.
+    pub health: f64,
+    /// Share of the living opponents that are shorter.
+    pub length_rank: f64,
+    /// Bonus for being strictly longer than all living opponents, which wins
+    /// all head to heads.
+    pub longest: f64,
+    /// Length of the snake, see [`Weights::length_knee`].
+    pub length: f64,
+    /// Share of half the board perimeter, beyond which every additional
+    /// segment is only rewarded by [`Weights::length_decay`].
+    pub length_knee: f64,
+    /// Factor of the length reward beyond the knee, which discourages
+    /// the snake from over-eating into self-traps.
+    pub length_decay: f64,
+    /// Closeness of the own tail, which can be chased to escape traps.
+    pub tail: f64,
+    /// Number of valid moves within two turns, see [`Game::mobility`].
+    pub mobility: f64,
+}
+
+impl Weights {
+    /// Length reward with diminishing returns beyond the knee.
+    fn decayed_length(&self, length: usize, width: usize, height: usize) -> f64 {
+        let knee = self.length_knee * (width + height) as f64;
+        let length = length as f64;
+        length.min(knee) + self.length_decay * (length - knee).max(0.0)
+    }
+}
+
+/// Unweighted terms of the position evaluation, see [`Game::evaluate_terms`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Terms {
+    /// Share of the cells the snake reaches first.
+    pub area: f64,
+    /// Health relative to the maximum.
+    pub health: f64,
+    /// Decayed length (with the knee and decay of the weights) relative to
+    /// the board size.
+    pub length: f64,
+    /// Share of the living opponents that are shorter.
+    pub length_rank: f64,
+    /// One if the snake is strictly longer than all living opponents.
+    pub longest: f64,
+    /// Inverse of the number of moves to the own tail, zero if it is
+    /// unreachable.
+    pub tail: f64,
+    /// Share of the maximum number of valid moves within two turns.
+    pub mobility: f64,
+}
+
+impl Terms {
+    /// Names of the terms in the order of [`Terms::weighted`].
+    pub const NAMES: [&'static str; 7] = [
+        "area",
+        "health",
+        "length",
+        "length_rank",
+        "longest",
+        "tail",
+        "mobility",
+    ];
+
+    /// Returns the terms multiplied by their weights.
+    pub fn weighted(&self, weights: &Weights) -> [f64; 7] {
+        [
+            weights.area * self.area,
+            weights.health * self.health,
+            weights.length * self.length,
+            weights.length_rank * self.length_rank,
+            weights.longest * self.longest,
+            weights.tail * self.tail,
+            weights.mobility * self.mobility,
+        ]
+    }
+
+    /// Returns the weighted sum of the terms.
+    pub fn total(&self, weights: &Weights) -> f64 {
+        self.weighted(weights).iter().sum()
+    }
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            area: 1.0,
+            health: 0.2,
+            length_rank: 0.5,
+            longest: 1.0,
+            length: 1.0,
+            length_knee: 1.0,
+            length_decay: 0.1,
+            tail: 0.1,
+            mobility: 0.1,
+        }
+    }
+}
+
+/// Memoizes [`Game::evaluate_with`] for positions that recur during a search.
+///
+/// The evaluation is pure for a set of weights, so the results are keyed by
+/// the position ([`Game::encode`]), the health of the snakes and the rated
+/// snake.
+/// The cache is cleared when it is full.
+#[derive(Debug, Clone)]
+pub struct EvalCache {
+    weights: Weights,
+    capacity: usize,
+    entries: HashMap<(u128, u8), f64>,
+    hits: usize,
+    misses: usize,
+}
+
+impl EvalCache {
+    /// Creates a cache for evaluations with the weights that holds at most
+    /// `capacity` results.
+    pub fn new(weights: Weights, capacity: usize) -> Self {
+        Self {
+            weights,
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the cached evaluation of the position or evaluates it.
+    pub fn evaluate(&mut self, game: &Game, snake: u8) -> f64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write(&game.encode().to_le_bytes());
+        hasher.write(&[game.wrap as u8]);
+        for s in &game.snakes {
+            hasher.write(&[s.health]);
+        }
+        let key = (hasher.finish(), snake);
+
+        if let Some(&value) = self.entries.get(&key) {
+            self.hits += 1;
+            return value;
+        }
+        self.misses += 1;
+        let value = game.evaluate_with(snake, &self.weights);
+        if self.entries.len() >= self.capacity {
+            self.entries.clear();
+        }
+        self.entries.insert(key, value);
+        value
+    }
+
+    /// Number of evaluations that were answered from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of evaluations that were computed.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+/// Distance of cells that cannot be reached.
+pub const UNREACHABLE: u16 = u16::MAX;
+
+/// Partition of the board into the cells the snakes reach first.
+#[derive(Debug, Clone)]
+pub struct Voronoi {
+    /// Distance maps of all snakes (see [`Game::distance_map`]).
+    pub distances: Vec<Vec<u16>>,
+    /// Snake that reaches each cell first, `None` if unreachable or tied.
+    pub owners: Vec<Option<u8>>,
+}
+
+impl Voronoi {
+    fn new(distances: Vec<Vec<u16>>) -> Self {
+        let cells = distances.first().map_or(0, Vec::len);
+        let owners = (0..cells)
+            .map(|i| {
+                let mut owner = None;
+                let mut best = UNREACHABLE;
+                for (snake, map) in distances.iter().enumerate() {
+                    if map[i] < best {
+                        best = map[i];
+                        owner = Some(snake as u8);
+                    } else if map[i] == best {
+                        owner = None;
+                    }
+                }
+                owner
+            })
+            .collect();
+        Self { distances, owners }
+    }
+
+    /// Returns the number of cells owned by the snake.
+    pub fn area(&self, snake: u8) -> usize {
+        self.owners.iter().filter(|&&o| o == Some(snake)).count()
+    }
+}
+
+impl Game {
+    /// Returns the number of cells that are reachable from `start` over
+    /// [`Game::is_walkable`] cells.
+    /// `start` itself is included, even if it is owned (e.g. a head).
+    pub fn flood_fill(&self, start: Vec2D) -> usize {
+        self.distance_map(start)
+            .iter()
+            .filter(|&&d| d != UNREACHABLE)
+            .count()
+    }
+
+    /// Returns the number of moves from `start` to every cell over
+    /// [`Game::is_walkable`] cells, or [`UNREACHABLE`].
+    /// The map is indexed like the cells of the grid.
+    pub fn distance_map(&self, start: Vec2D) -> Vec<u16> {
+        self.distance_map_with(start, |p| self.is_walkable(p))
+    }
+
+    /// Like [`Game::distance_map`], with a custom check for passable cells.
+    fn distance_map_with(&self, start: Vec2D, passable: impl Fn(Vec2D) -> bool) -> Vec<u16> {
+        let mut distances = vec![UNREACHABLE; self.grid.cells.len()];
+        if !self.grid.has(start) {
+            return distances;
+        }
+        let width = self.grid.width;
+        let index = |p: Vec2D| p.x as usize + p.y as usize * width;
+
+        let mut queue = VecDeque::new();
+        distances[index(start)] = 0;
+        queue.push_back(start);
+
+        while let Some(p) = queue.pop_front() {
+            profile::count(|c| c.flood_fill_cells += 1);
+            let distance = distances[index(p)];
+            for d in Direction::all() {
+                let next = self.neighbor(p, d);
+                if self.grid.has(next) && distances[index(next)] == UNREACHABLE && passable(next) {
+                    distances[index(next)] = distance + 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Returns the number of moves from the head of the snake to a cell next
+    /// to its tail (plus one), or [`UNREACHABLE`].
+    pub fn tail_distance(&self, snake: u8) -> u16 {
+        let my = &self.snakes[snake as usize];
+        let tail = my.body[0];
+        let distances = self.distance_map(my.head());
+        let index = |p: Vec2D| p.x as usize + p.y as usize * self.grid.width;
+        Direction::all()
+            .into_iter()
+            .map(|d| self.neighbor(tail, d))
+            .filter(|&p| self.grid.has(p))
+            .map(|p| {
+                if p == my.head() {
+                    1
+                } else {
+                    distances[index(p)].saturating_add(1)
+                }
+            })
+            .min()
+            .unwrap_or(UNREACHABLE)
+    }
+
+    /// Returns the number of cells the snake can reach in exactly two moves.
+    ///
+    /// Unlike [`Game::flood_fill`], this measures how many escape routes the
+    /// local region of the head has, which is cheap and predicts traps.
+    pub fn exit_count(&self, snake: u8) -> usize {
+        if !self.snake_is_alive(snake) {
+            return 0;
+        }
+        self.exits(self.snakes[snake as usize].head())
+    }
+
+    /// Returns the number of valid moves of the snake within two turns: its
+    /// valid moves and the moves that follow each of them.
+    ///
+    /// The opponents are assumed to stay where they are.
+    /// Cheaper than [`Game::flood_fill`], a low mobility signals a trap.
+    pub fn mobility(&self, snake: u8) -> usize {
+        if !self.snake_is_alive(snake) {
+            return 0;
+        }
+        let head = self.snakes[snake as usize].head();
+        self.valid_moves(snake)
+            .map(|d| {
+                let p = self.neighbor(head, d);
+                1 + Direction::all()
+                    .into_iter()
+                    .filter(|&d| {
+                        let q = self.neighbor(p, d);
+                        q != head && self.is_walkable(q)
+                    })
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Number of cells two moves away from `start` over walkable cells.
+    fn exits(&self, start: Vec2D) -> usize {
+        self.distance_map_with(start, |p| self.is_walkable(p))
+            .iter()
+            .filter(|&&d| d == 2)
+            .count()
+    }
+
+    /// Partitions the board into the cells each snake reaches first.
+    pub fn voronoi(&self) -> Voronoi {
+        let distances = self
+            .snakes
+            .iter()
+            .map(|s| {
+                if s.alive() {
+                    self.distance_map(s.head())
+                } else {
+                    vec![UNREACHABLE; self.grid.cells.len()]
+                }
+            })
+            .collect();
+        Voronoi::new(distances)
+    }
+
+    /// Updates the `base` partition of this game after the snake moved in the
+    /// given direction.
+    ///
+    /// Only the distances of the moved snake are recomputed, the others are
+    /// taken from `base`. They ignore the new head and the freed tail, which
+    /// makes the result an approximation of a full recomputation.
+    pub fn voronoi_delta(&self, base: &Voronoi, snake: u8, dir: Direction) -> Voronoi {
+        let mut distances = base.distances.clone();
+        distances[snake as usize] = if self.move_is_valid(snake, dir) {
+            let my = &self.snakes[snake as usize];
+            let head = self.neighbor(my.head(), dir);
+            // The next tail moves as well unless it is stacked
+            let tail = (my.body.len() > 2 && my.body[1] != my.body[2]).then_some(my.body[1]);
+            self.distance_map_with(head, |p| self.is_walkable(p) || Some(p) == tail)
+        } else {
+            vec![UNREACHABLE; self.grid.cells.len()]
+        };
+        Voronoi::new(distances)
+    }
+
+    /// Returns the number of cells the snake can reach if `blocked` is
+    /// occupied as well (e.g. by the head of an opponent).
+    pub fn area_without(&self, snake: u8, blocked: Vec2D) -> usize {
+        if !self.snake_is_alive(snake) {
+            return 0;
+        }
+        let head = self.snakes[snake as usize].head();
+        self.distance_map_with(head, |p| p != blocked && self.is_walkable(p))
+            .into_iter()
+            .filter(|&d| d != UNREACHABLE)
+            .count()
+    }
+
+    /// Returns the move of the snake that reduces the reachable area of the
+    /// nearest opponent the most, e.g. to cut it off from parts of the board.
+    ///
+    /// Moves that might lose a head to head are skipped.
+    /// Returns `None` if no move reduces the area of the opponent.
+    pub fn best_space_denial(&self, snake: u8) -> Option<Direction> {
+        if !self.snake_is_alive(snake) {
+            return None;
+        }
+        let head = self.snakes[snake as usize].head();
+        let enemy = self
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|&(i, s)| i != snake as usize && s.alive())
+            .min_by_key(|&(_, s)| {
+                let diff = s.head() - head;
+                diff.x.abs() + diff.y.abs()
+            })
+            .map(|(i, _)| i as u8)?;
+        let before = self.flood_fill(self.snakes[enemy as usize].head());
+
+        self.valid_moves(snake)
+            .map(|d| (d, self.neighbor(head, d)))
+            .filter(|&(_, p)| !self.head_to_head_risk(snake, p))
+            .map(|(d, p)| (before.saturating_sub(self.area_without(enemy, p)), d))
+            .filter(|&(reduction, _)| reduction > 0)
+            // Prefer the first of equally good moves
+            .max_by(|a, b| a.0.cmp(&b.0).then((b.1 as u8).cmp(&(a.1 as u8))))
+            .map(|(_, d)| d)
+    }
+
+    /// Returns the valid move whose destination is the fewest moves away from
+    /// a cell without hazard, to leave the hazards as fast as possible when
+    /// every move enters one.
+    ///
+    /// Equally good moves are taken in the order of [`Direction::all`].
+    /// Without valid moves, the [`fallback_move`] is returned.
+    pub fn least_hazard_move(&self, snake: u8) -> Direction {
+        let head = self.snakes[snake as usize].head();
+        self.valid_moves(snake)
+            .min_by_key(|&d| self.hazard_exit_distance(self.neighbor(head, d)))
+            .unwrap_or_else(|| fallback_move(self, snake))
+    }
+
+    /// Returns the number of moves from `p` to the nearest walkable cell
+    /// without hazard, or [`UNREACHABLE`].
+    pub fn hazard_exit_distance(&self, p: Vec2D) -> u16 {
+        if !self.grid.is_hazardous(p) {
+            return 0;
+        }
+        self.distance_map(p)
+            .into_iter()
+            .zip(&self.grid.cells)
+            .filter(|&(d, cell)| d != UNREACHABLE && !cell.hazard)
+            .map(|(d, _)| d)
+            .min()
+            .unwrap_or(UNREACHABLE)
+    }
+
+    /// Predicts the number of turns until each cell becomes hazardous, if the
+    /// hazards grow by one row or column every `shrink_turns` turns, like in
+    /// royale.
+    ///
+    /// The side that grows is random, so the forecast pessimistically grows
+    /// from every border and every current hazard.
+    /// Hazardous cells are zero and the map is indexed like the cells of the grid.
+    pub fn hazard_forecast(&self, shrink_turns: usize) -> Vec<u16> {
+        let shrink_turns = shrink_turns.max(1);
+        let Grid { width, height, .. } = self.grid;
+        let index = |p: Vec2D| p.x as usize + p.y as usize * width;
+
+        // distance to the nearest hazard
+        let mut distances = vec![UNREACHABLE; self.grid.cells.len()];
+        let mut queue = VecDeque::new();
+        for (i, cell) in self.grid.cells.iter().enumerate() {
+            if cell.hazard {
+                distances[i] = 0;
+                queue.push_back(v2((i % width) as _, (i / width) as _));
+            }
+        }
+        while let Some(p) = queue.pop_front() {
+            let distance = distances[index(p)];
+            for d in Direction::all() {
+                let next = p.apply(d);
+                if self.grid.has(next) && distances[index(next)] == UNREACHABLE {
+                    distances[index(next)] = distance + 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let next_shrink = shrink_turns - self.turn % shrink_turns;
+        (0..height as i16)
+            .flat_map(|y| (0..width as i16).map(move |x| v2(x, y)))
+            .map(|p| {
+                let border =
+                    p.x.min(p.y)
+                        .min(width as i16 - 1 - p.x)
+                        .min(height as i16 - 1 - p.y);
+                let shrinks = distances[index(p)].min(border as u16 + 1) as usize;
+                if shrinks == 0 {
+                    0
+                } else {
+                    (next_shrink + (shrinks - 1) * shrink_turns).min(u16::MAX as usize) as u16
+                }
+            })
+            .collect()
+    }
+
+    /// Returns if an equal or longer snake could move onto `p` as well,
+    /// which might lose a head to head.
+    pub fn head_to_head_risk(&self, snake: u8, p: Vec2D) -> bool {
+        self.threatened_cells(snake).any(|q| q == p)
+    }
+
+    /// Returns the cells an equal or longer opponent of snake 0 could move
+    /// onto in the next turn, where snake 0 might lose a head to head (see
+    /// [`Game::head_to_head_risk`]).
+    /// The map is indexed like the cells of the grid.
+    pub fn danger_map(&self) -> Vec<bool> {
+        let mut danger = vec![false; self.grid.cells.len()];
+        if self.snakes.first().is_some_and(|s| s.alive()) {
+            for p in self.threatened_cells(0).filter(|&p| self.grid.has(p)) {
+                danger[p.x as usize + p.y as usize * self.grid.width] = true;
+            }
+        }
+        danger
+    }
+
+    /// Cells the living opponents that are at least as long as the snake can
+    /// move onto with a valid move.
+    fn threatened_cells(&self, snake: u8) -> impl Iterator<Item = Vec2D> + '_ {
+        let len = self.snakes[snake as usize].body.len();
+        self.snakes
+            .iter()
+            .enumerate()
+            .filter(move |&(i, s)| i != snake as usize && s.alive() && s.body.len() >= len)
+            .flat_map(move |(i, s)| {
+                self.valid_moves(i as u8)
+                    .map(move |d| self.neighbor(s.head(), d))
+            })
+    }
+
+    /// Returns the valid moves that cannot lose in this turn, whatever the
+    /// opponents do.
+    ///
+    /// No equal or longer snake can move onto the destination (see
+    /// [`Game::head_to_head_risk`]), it is not the tail of an opponent that
+    /// might eat and keep its tail, and the area that is reachable from it
+    /// is not smaller than the body.
+    pub fn guaranteed_safe_moves(&self, snake: u8) -> Vec<Direction> {
+        let Some(my) = self.snakes.get(snake as usize).filter(|s| s.alive()) else {
+            return Vec::new();
+        };
+        let might_eat = |s: &Snake| {
+            Direction::all().iter().any(|&d| {
+                let p = self.neighbor(s.head(), d);
+                self.grid.has(p) && self.grid[p].t == CellT::Food
+            })
+        };
+
+        self.valid_moves(snake)
+            .filter(|&d| {
+                let p = self.neighbor(my.head(), d);
+                !self.head_to_head_risk(snake, p)
+                    && !self.snakes.iter().enumerate().any(|(i, s)| {
+                        i != snake as usize && s.alive() && s.body[0] == p && might_eat(s)
+                    })
+                    && self.flood_fill(p) >= my.body.len()
+            })
+            .collect()
+    }
+
+    /// Evaluates the position for the snake with the default [`Weights`].
+    pub fn evaluate(&self, snake: u8) -> f64 {
+        self.evaluate_with(snake, &Weights::default())
+    }
+
+    /// Evaluates the position for the snake, higher is better.
+    /// Dead snakes have the lowest possible score.
+    pub fn evaluate_with(&self, snake: u8, weights: &Weights) -> f64 {
+        match self.evaluate_terms(snake, weights) {
+            Some(terms) => terms.total(weights),
+            None => f64::MIN,
+        }
+    }
+
+    /// Returns the terms of [`Game::evaluate_with`], or `None` if the snake
+    /// is dead.
+    pub fn evaluate_terms(&self, snake: u8, weights: &Weights) -> Option<Terms> {
+        profile::count(|c| c.evaluations += 1);
+        if !self.snake_is_alive(snake) {
+            return None;
+        }
+        let my = &self.snakes[snake as usize];
+        let cells = self.grid.cells.len() as f64;
+
+        let mut terms = Terms {
+            area: self.voronoi().area(snake) as f64 / cells,
+            health: my.health as f64 / 100.0,
+            length: weights.decayed_length(my.body.len(), self.grid.width, self.grid.height)
+                / cells,
+            ..Terms::default()
+        };
+
+        let (mut opponents, mut shorter) = (0, 0);
+        for (i, s) in self.snakes.iter().enumerate() {
+            if i != snake as usize && s.alive() {
+                opponents += 1;
+                if s.body.len() < my.body.len() {
+                    shorter += 1;
+                }
+            }
+        }
+        if opponents > 0 {
+            terms.length_rank = shorter as f64 / opponents as f64;
+            if shorter == opponents {
+                terms.longest = 1.0;
+            }
+        }
+        if weights.tail != 0.0 {
+            terms.tail = match self.tail_distance(snake) {
+                UNREACHABLE => 0.0,
+                d => 1.0 / d.max(1) as f64,
+            };
+        }
+        if weights.mobility != 0.0 {
+            terms.mobility = self.mobility(snake) as f64 / MAX_MOBILITY as f64;
+        }
+        Some(terms)
+    }
+
+    /// Evaluates the position after each valid move of the snake, assuming
+    /// the opponents keep their heading (see [`Game::predicted_head`]).
+    ///
+    /// The terms are `None` if the snake dies with the move.
+    /// Dead snakes have no moves and dead opponents are skipped.
+    pub fn evaluate_moves(&self, snake: u8, weights: &Weights) -> Vec<(Direction, Option<Terms>)> {
+        let mut moves: Vec<Direction> = (0..self.snakes.len() as u8)
+            .map(|i| {
+                if self.snakes[i as usize].alive() {
+                    self.predicted_move(i)
+                } else {
+                    Direction::Up
+                }
+            })
+            .collect();
+        let mut next = self.clone();
+        self.valid_moves(snake)
+            .map(|dir| {
+                moves[snake as usize] = dir;
+                self.clone_into(&mut next);
+                next.step(&moves);
+                (dir, next.evaluate_terms(snake, weights))
+            })
+            .collect()
+    }
+
+    /// Returns if the snake survives `plies` turns after the move, if the
+    /// opponents reply with their most dangerous move.
+    ///
+    /// Each opponent in turn tries all its moves while the others keep their
+    /// heading (see [`Game::predicted_move`]).
+    /// The snake survives a ply if it is alive and, for the last ply, has a
+    /// valid move left, or else has a move that survives the remaining plies.
+    pub fn survives_replies(&self, snake: u8, dir: Direction, plies: u8) -> bool {
+        self.survives_replies_cancellable(snake, dir, plies, &|| false)
+    }
+
+    /// Like [`Game::survives_replies`], but gives up as soon as `cancelled`
+    /// returns true, then the move does not survive.
+    pub fn survives_replies_cancellable(
+        &self,
+        snake: u8,
+        dir: Direction,
+        plies: u8,
+        cancelled: &impl Fn() -> bool,
+    ) -> bool {
+        let mut moves: Vec<Direction> = (0..self.snakes.len() as u8)
+            .map(|i| self.predicted_move(i))
+            .collect();
+        moves[snake as usize] = dir;
+
+        let mut replies: Vec<Option<(u8, Direction)>> = (0..self.snakes.len() as u8)
+            .filter(|&i| i != snake)
+            .flat_map(|i| self.valid_moves(i).map(move |d| Some((i, d))))
+            .collect();
+        // Without replies, only the predicted moves are simulated
+        if replies.is_empty() {
+            replies.push(None);
+        }
+
+        let mut next = self.clone();
+        replies.into_iter().all(|reply| {
+            if cancelled() {
+                return false;
+            }
+            let mut moves = moves.clone();
+            if let Some((i, d)) = reply {
+                moves[i as usize] = d;
+            }
+            self.clone_into(&mut next);
+            next.step(&moves);
+            if !next.snake_is_alive(snake) {
+                return false;
+            }
+            let mut valid = next.valid_moves(snake);
+            if plies <= 1 {
+                valid.next().is_some()
+            } else {
+                valid.any(|d| next.survives_replies_cancellable(snake, d, plies - 1, cancelled))
+            }
+        })
+    }
+
+    /// Predicts the next move of the snake, assuming it keeps its current
+    /// heading, or its fallback move if it has no heading.
+    pub fn predicted_move(&self, snake: u8) -> Direction {
+        self.heading(snake)
+            .unwrap_or_else(|| fallback_move(self, snake))
+    }
+
+    /// Predicts the next head position of the snake, assuming it keeps its
+    /// current heading.
+    ///
+    /// Snakes without a heading (e.g. on their first turn) keep their head.
+    pub fn predicted_head(&self, snake: u8) -> Vec2D {
+        let body = &self.snakes[snake as usize].body;
+        let head = body[body.len() - 1];
+        match body.len().checked_sub(2).map(|i| body[i]) {
+            Some(neck) if neck != head => Direction::all()
+                .into_iter()
+                .find(|&d| self.neighbor(neck, d) == head)
+                .map_or(head, |d| self.neighbor(head, d)),
+            _ => head,
+        }
+    }
+
+    /// Returns the food the snake cannot reach (see [`Game::distance_map`]),
+    /// e.g. behind a wall of bodies.
+    ///
+    /// All food is unreachable for dead snakes.
+    pub fn unreachable_food(&self, snake: u8) -> Vec<Vec2D> {
+        let distances = match self.snakes.get(snake as usize).filter(|s| s.alive()) {
+            Some(my) => self.distance_map(my.head()),
+            None => vec![UNREACHABLE; self.grid.cells.len()],
+        };
+        (0..self.grid.height as i16)
+            .flat_map(|y| (0..self.grid.width as i16).map(move |x| v2(x, y)))
+            .filter(|&p| {
+                self.grid[p].t == CellT::Food
+                    && distances[p.x as usize + p.y as usize * self.grid.width] == UNREACHABLE
+            })
+            .collect()
+    }
+
+    /// Returns the food that is closest to the head of the snake.
+    pub fn nearest_food(&self, snake: u8) -> Option<Vec2D> {
+        if !self.snake_is_alive(snake) {
+            return None;
+        }
+        let head = self.snakes[snake as usize].head();
+        let distance = |p: Vec2D| {
+            let diff = p - head;
+            (diff.x as f64).hypot(diff.y as f64)
+        };
+        (0..self.grid.height as i16)
+            .flat_map(|y| (0..self.grid.width as i16).map(move |x| v2(x, y)))
+            .filter(|&p| self.grid[p].t == CellT::Food)
+            .min_by(|&a, &b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(Ordering::Equal)
+            })
+    }
+
+    /// Returns the valid moves of the snake that start a shortest path to its
+    /// nearest reachable food (by moves, see [`Game::distance_map`]).
+    pub fn food_moves(&self, snake: u8) -> Vec<Direction> {
+        if !self.snake_is_alive(snake) {
+            return Vec::new();
+        }
+        let head = self.snakes[snake as usize].head();
+        let index = |p: Vec2D| p.x as usize + p.y as usize * self.grid.width;
+
+        let distances = self.distance_map(head);
+        let Some(food) = (0..self.grid.height as i16)
+            .flat_map(|y| (0..self.grid.width as i16).map(move |x| v2(x, y)))
+            .filter(|&p| self.grid[p].t == CellT::Food && distances[index(p)] != UNREACHABLE)
+            .min_by_key(|&p| distances[index(p)])
+        else {
+            return Vec::new();
+        };
+
+        // Distances to the food, which decrease along the shortest paths
+        let to_food = self.distance_map(food);
+        let distance = |d: Direction| to_food[index(self.neighbor(head, d))];
+        let Some(best) = self.valid_moves(snake).map(distance).min() else {
+            return Vec::new();
+        };
+        self.valid_moves(snake)
+            .filter(|&d| best != UNREACHABLE && distance(d) == best)
+            .collect()
+    }
+
+    /// Returns the nearest food the snake can reach before starving,
+    /// together with the path to it.
+    pub fn food_target(&self, snake: u8, heuristic: Heuristic) -> Option<(Vec2D, Vec<Vec2D>)> {
+        self.food_targets(snake, heuristic).next()
+    }
+
+    /// Returns all food the snake can reach before starving, ordered by
+    /// their distance, together with the paths to them.
+    pub fn food_targets(
+        &self,
+        snake: u8,
+        heuristic: Heuristic,
+    ) -> impl Iterator<Item = (Vec2D, Vec<Vec2D>)> + '_ {
+        self.food_targets_budget(snake, heuristic, None)
+    }
+
+    /// Like [`Game::food_targets`], but each search expands at most
+    /// `max_nodes` nodes.
+    ///
+    /// For food that is not found within the budget, the path is the partial
+    /// path toward it (see [`Game::a_star_budget`]), which ends before the
+    /// food.
+    pub fn food_targets_budget(
+        &self,
+        snake: u8,
+        heuristic: Heuristic,
+        max_nodes: Option<usize>,
+    ) -> impl Iterator<Item = (Vec2D, Vec<Vec2D>)> + '_ {
+        let mut food: Vec<Vec2D> = Vec::new();
+        if self.snake_is_alive(snake) {
+            let head = self.snakes[snake as usize].head();
+            food = (0..self.grid.height as i16)
+                .flat_map(|y| (0..self.grid.width as i16).map(move |x| v2(x, y)))
+                .filter(|&p| self.grid[p].t == CellT::Food)
+                .collect();
+            food.sort_by_key(|&p| {
+                let diff = p - head;
+                diff.x as i32 * diff.x as i32 + diff.y as i32 * diff.y as i32
+            });
+        }
+
+        food.into_iter().filter_map(move |target| {
+            let head = self.snakes[snake as usize].head();
+            let path = match max_nodes {
+                Some(max_nodes) => self.a_star_budget(head, target, heuristic, max_nodes)?,
+                None => self.a_star(head, target, &[0.0; 4], heuristic)?,
+            };
+            self.health_after_path(snake, &path)?;
+            Some((target, path))
+        })
+    }
+
+    /// Returns the area that is reachable after the snake followed the path
+    /// and ate the food at its end (see [`Game::flood_fill`]).
+    ///
+    /// The other snakes are assumed to stay where they are.
+    pub fn space_after_path(&self, snake: u8, path: &[Vec2D]) -> usize {
+        let Some(my) = self.snakes.get(snake as usize).filter(|s| s.alive()) else {
+            return 0;
+        };
+        let Some(&end) = path.last() else {
+            return 0;
+        };
+
+        let mut game = self.clone();
+        for &p in &my.body {
+            game.grid[p].t = CellT::Free;
+        }
+        // The body grows by one when eating
+        let len = my.body.len() + 1;
+        let body: Vec<Vec2D> = my.body.iter().chain(path.iter().skip(1)).copied().collect();
+        for &p in &body[body.len().saturating_sub(len)..] {
+            game.grid[p].t = CellT::Owned;
+        }
+        game.flood_fill(end)
+    }
+
+    /// Returns the health of the snake after following the path, which starts
+    /// at its head, or `None` if it starves on the way.
+    ///
+    /// Like in [`Game::step`], food on the path restores the health.
+    pub fn health_after_path(&self, snake: u8, path: &[Vec2D]) -> Option<u8> {
+        let mut health = self.snakes.get(snake as usize)?.health;
+        if health == 0 {
+            return None;
+        }
+        for &p in path.iter().skip(1) {
+            if self.grid[p].t == CellT::Food {
+                health = 100;
+            } else {
+                health = health.saturating_sub(self.step_costs(p) as u8);
+                if health == 0 {
+                    return None;
+                }
+            }
+        }
+        Some(health)
+    }
+
+    /// Returns all valid moves of the snake, ordered from best to worst.
+    ///
+    /// The moves are scored by their safety from head to head collisions with
+    /// equal or longer snakes, the reachable area, its exits and the path costs to the
+    /// [`Game::food_target`].
+    pub fn ranked_moves(&self, snake: u8) -> Vec<(Direction, f64)> {
+        self.ranked_moves_by(snake, Heuristic::default())
+    }
+
+    /// Like [`Game::ranked_moves`], with the `heuristic` of the path finding
+    /// to the nearest food.
+    pub fn ranked_moves_by(&self, snake: u8, heuristic: Heuristic) -> Vec<(Direction, f64)> {
+        let target = self.food_target(snake, heuristic).map(|(t, _)| t);
+        self.ranked_moves_to(snake, target, heuristic)
+    }
+
+    /// Like [`Game::ranked_moves_by`], with the food `target` chosen by the
+    /// caller.
+    pub fn ranked_moves_to(
+        &self,
+        snake: u8,
+        target: Option<Vec2D>,
+        heuristic: Heuristic,
+    ) -> Vec<(Direction, f64)> {
+        self.ranked_moves_with(snake, target, heuristic, HAZARD_WEIGHT, None, 0.0)
+    }
+
+    /// Like [`Game::ranked_moves_to`], with a custom reward for moves into
+    /// hazards, an optional [`Game::hazard_forecast`] and a food bias.
+    ///
+    /// Without the reward, hazards are only avoided by the health costs of
+    /// the path to the target.
+    /// With the forecast, cells are also hazardous if the hazards grow into
+    /// them before the snake arrives.
+    /// The `food_bias` (see [`AStarOptions::food_bias`]) makes paths that
+    /// clip food on the way cheaper.
+    pub fn ranked_moves_with(
+        &self,
+        snake: u8,
+        target: Option<Vec2D>,
+        heuristic: Heuristic,
+        hazard_weight: f64,
+        hazard_forecast: Option<&[u16]>,
+        food_bias: f64,
+    ) -> Vec<(Direction, f64)> {
+        let Some(my) = self.snakes.get(snake as usize).filter(|s| s.alive()) else {
+            return Vec::new();
+        };
+        let cells = (self.grid.width * self.grid.height) as f64;
+        let width = self.grid.width;
+        let hazardous = |p: Vec2D, arrival: usize| {
+            self.grid.is_hazardous(p)
+                || hazard_forecast.is_some_and(|forecast| {
+                    self.grid.has(p)
+                        && forecast[p.x as usize + p.y as usize * width] as usize <= arrival
+                })
+        };
+        // the path search starts after the first move
+        let path_forecast: Option<Vec<u16>> =
+            hazard_forecast.map(|forecast| forecast.iter().map(|t| t.saturating_sub(1)).collect());
+
+        let mut moves: Vec<(Direction, f64)> = self
+            .valid_moves(snake)
+            .map(|d| {
+                profile::count(|c| c.evaluations += 1);
+                let p = self.neighbor(my.head(), d);
+                let mut score = 0.0;
+
+                if self.head_to_head_risk(snake, p) {
+                    score -= SAFETY_WEIGHT;
+                }
+
+                let area = self.flood_fill(p);
+                if area < my.body.len() {
+                    score -= TRAP_WEIGHT;
+                }
+                score += SPACE_WEIGHT * area as f64 / cells;
+                score += EXIT_WEIGHT * self.exits(p) as f64 / MAX_EXITS as f64;
+
+                if hazardous(p, 1) {
+                    score += hazard_weight;
+                }
+
+                if let Some(target) = target {
+                    let path = self.grid.a_star_with(
+                        p,
+                        target,
+                        &[0.0; 4],
+                        heuristic,
+                        AStarOptions {
+                            food_bias,
+                            hazard_forecast: path_forecast.as_deref(),
+                            ..AStarOptions::default()
+                        },
+                        |p| self.is_walkable(p),
+                    );
+                    if let Some(path) = path {
+                        let food_bias = food_bias.clamp(0.0, 1.0);
+                        let costs: f64 = path
+                            .iter()
+                            .enumerate()
+                            .map(|(i, &p)| {
+                                let costs = if hazardous(p, i + 1) {
+                                    HAZARD_DAMAGE as f64
+                                } else {
+                                    1.0
+                                };
+                                if self.grid[p].t == CellT::Food {
+                                    costs - food_bias
+                                } else {
+                                    costs
+                                }
+                            })
+                            .sum();
+                        score += FOOD_WEIGHT / (1.0 + costs);
+                    }
+                }
+
+                (d, score)
+            })
+            .collect();
+
+        moves.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        moves
+    }
+
+    /// Health costs of moving onto `p`.
+    fn step_costs(&self, p: Vec2D) -> usize {
+        if self.grid.is_hazardous(p) {
+            HAZARD_DAMAGE as usize
+        } else {
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn eval_flood_fill() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            0 < . . .
+            o ^ < . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.flood_fill(Vec2D::new(0, 0)), 1);
+        // Including the moving tail
+        assert_eq!(game.flood_fill(Vec2D::new(0, 2)), 16);
+        // The head itself is included
+        assert_eq!(game.flood_fill(Vec2D::new(0, 1)), 18);
+        assert_eq!(game.flood_fill(Vec2D::new(-1, 0)), 0);
+    }
+
+    #[test]
+    fn eval_exit_count() {
+        use super::*;
+        logging();
+
+        // Only one exit out of the corridor
+        let game = Game::parse(
+            r#"
+            > > > > 1
+            . . . . 0
+            > > > > ^"#,
+        )
+        .unwrap();
+        assert_eq!(game.exit_count(0), 1);
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . 0 . .
+            . . . . .
+            . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.exit_count(0), 8);
+    }
+
+    #[test]
+    fn eval_wrapped() {
+        use super::*;
+        logging();
+
+        // Snake 1 walls off the left edge, its stacked tail does not move
+        let mut game = Game::parse(
+            r#"
+            . v*2 . . .
+            0 v . . .
+            . 1 . . ."#,
+        )
+        .unwrap();
+        // Index of (4, 1)
+        let target = 4 + 5;
+        assert_eq!(game.flood_fill(v2(0, 1)), 3);
+        assert!(!game.move_is_valid(0, Direction::Left));
+        let voronoi = game.voronoi();
+        assert_eq!(voronoi.owners[target], Some(1));
+        let area = voronoi.area(0);
+
+        // Over the edges snake 0 can reach the whole board
+        game.wrap = true;
+        assert_eq!(game.flood_fill(v2(0, 1)), 12);
+        assert!(game.move_is_valid(0, Direction::Left));
+        let voronoi = game.voronoi();
+        info!("{voronoi:?}");
+        assert_eq!(voronoi.distances[0][target], 1);
+        assert_eq!(voronoi.owners[target], Some(0));
+        assert!(voronoi.area(0) > area);
+
+        game.step(&[Direction::Left, Direction::Right]);
+        assert_eq!(game.snakes[0].head(), v2(4, 1));
+    }
+
+    #[test]
+    fn eval_health_after_path() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . o
+            . . 0 . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+        game.grid[v2(3, 1)].hazard = true;
+        game.snakes[0].health = 20;
+
+        let path = [v2(2, 1), v2(3, 1), v2(4, 1)];
+        assert_eq!(game.health_after_path(0, &path), Some(4));
+        let path = [v2(2, 1), v2(2, 2), v2(3, 2), v2(4, 2)];
+        assert_eq!(game.health_after_path(0, &path), Some(100));
+
+        game.snakes[0].health = 15;
+        assert_eq!(game.health_after_path(0, &[v2(2, 1), v2(3, 1)]), None);
+    }
+
+    #[test]
+    fn eval_space_after_path() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            0 < . . .
+            o ^ < . ."#,
+        )
+        .unwrap();
+        // Eating closes the dead end
+        assert_eq!(game.space_after_path(0, &[v2(0, 1), v2(0, 0)]), 1);
+        assert_eq!(game.space_after_path(0, &[v2(0, 1), v2(0, 2)]), 16);
+    }
+
+    #[test]
+    fn eval_voronoi_delta() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . 0 < < . . .
+            . . . . . . .
+            . . . . . 1 .
+            . . . . . ^ .
+            . . . . . ^ ."#,
+        )
+        .unwrap();
+        let base = game.voronoi();
+
+        for dir in game.valid_moves(0).collect::<Vec<_>>() {
+            let delta = game.voronoi_delta(&base, 0, dir);
+
+            // Apply the move only to snake 0
+            let mut moved = game.clone();
+            let snake = &mut moved.snakes[0];
+            let tail = snake.body.pop_front().unwrap();
+            moved.grid[tail].t = CellT::Free;
+            let head = snake.head().apply(dir);
+            snake.body.push_back(head);
+            moved.grid[head].t = CellT::Owned;
+            let full = moved.voronoi();
+
+            info!("{dir:?}: {} {}", delta.area(0), full.area(0));
+            assert_eq!(delta.distances[0], full.distances[0]);
+            for snake in 0..2 {
+                assert!(delta.area(snake).abs_diff(full.area(snake)) <= 2);
+            }
+        }
+
+        // Moving out of the board
+        let game = Game::parse("0 . .").unwrap();
+        let delta = game.voronoi_delta(&game.voronoi(), 0, Direction::Left);
+        assert_eq!(delta.area(0), 0);
+    }
+
+    #[test]
+    fn eval_evaluate_length() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . 1 .
+            . ^ . ^ .
+            . ^ . ^ ."#,
+        )
+        .unwrap();
+        // Grow the snakes with stacked tails, which does not change the grid
+        let grow = |game: &Game, snake: usize, n: usize| {
+            let mut game = game.clone();
+            let tail = game.snakes[snake].body[0];
+            for _ in 0..n {
+                game.snakes[snake].body.push_front(tail);
+            }
+            game
+        };
+        let longest = grow(&game, 0, 2);
+        let shortest = grow(&game, 1, 2);
+        info!(
+            "equal: {}, longest: {}, shortest: {}",
+            game.evaluate(0),
+            longest.evaluate(0),
+            shortest.evaluate(0)
+        );
+        assert!(longest.evaluate(0) > shortest.evaluate(0));
+        assert!(longest.evaluate(0) > game.evaluate(0));
+        assert!(game.evaluate(0) >= shortest.evaluate(0));
+
+        // Without weights, the length does not matter
+        let weights = Weights {
+            length_rank: 0.0,
+            longest: 0.0,
+            length: 0.0,
+            ..Weights::default()
+        };
+        assert_eq!(
+            longest.evaluate_with(0, &weights),
+            shortest.evaluate_with(0, &weights)
+        );
+
+        let mut dead = game.clone();
+        dead.step(&[Direction::Up, Direction::Left]);
+        dead.step(&[Direction::Up, Direction::Left]);
+        dead.step(&[Direction::Up, Direction::Left]);
+        assert_eq!(dead.evaluate(0), f64::MIN);
+    }
+
+    #[test]
+    fn eval_cache() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . o
+            . 0 . 1 .
+            . ^ . ^ .
+            . ^ . ^ ."#,
+        )
+        .unwrap();
+        let weights = Weights::default();
+        let mut cache = EvalCache::new(weights, 16);
+
+        let mut positions = Vec::new();
+        for dir in Direction::all() {
+            let mut child = game.clone();
+            child.step(&[dir, Direction::Up]);
+            positions.push(child);
+        }
+        for _ in 0..2 {
+            for child in &positions {
+                for snake in 0..2 {
+                    assert_eq!(
+                        cache.evaluate(child, snake),
+                        child.evaluate_with(snake, &weights)
+                    );
+                }
+            }
+        }
+        info!("hits {}, misses {}", cache.hits(), cache.misses());
+        assert_eq!(cache.misses(), 8);
+        assert_eq!(cache.hits(), 8);
+
+        // Health is part of the key, unlike in the encoding
+        let mut hungry = game.clone();
+        hungry.snakes[0].health = 10;
+        assert_eq!(hungry.encode(), game.encode());
+        assert_eq!(cache.evaluate(&game, 0), game.evaluate_with(0, &weights));
+        assert_eq!(
+            cache.evaluate(&hungry, 0),
+            hungry.evaluate_with(0, &weights)
+        );
+        assert_ne!(cache.evaluate(&hungry, 0), cache.evaluate(&game, 0));
+    }
+
+    #[test]
+    fn eval_evaluate_tail() {
+        use super::*;
+        logging();
+
+        let coiled = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . 0 < . .
+            . > ^ . .
+            . . . . ."#,
+        )
+        .unwrap();
+        let overextended = Game::parse(
+            r#"
+            . . . . .
+            0 < < < .
+            . . . . .
+            . . . . .
+            . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(coiled.tail_distance(0), 1);
+        assert_eq!(overextended.tail_distance(0), 5);
+
+        let weights = Weights::default();
+        let a = coiled.evaluate_terms(0, &weights).unwrap();
+        let b = overextended.evaluate_terms(0, &weights).unwrap();
+        info!("{a:?} {b:?}");
+        assert_eq!((a.area, a.health, a.length), (b.area, b.health, b.length));
+        assert_eq!((a.tail, b.tail), (1.0, 0.2));
+        assert!(coiled.evaluate(0) > overextended.evaluate(0));
+
+        // Without the weight, the term is skipped
+        let weights = Weights {
+            tail: 0.0,
+            mobility: 0.0,
+            ..Weights::default()
+        };
+        assert_eq!(
+            coiled.evaluate_with(0, &weights),
+            overextended.evaluate_with(0, &weights)
+        );
+    }
+
+    #[test]
+    fn eval_best_space_denial() {
+        use super::*;
+        logging();
+
+        // Moving in front of the gap cuts snake 1 off from the left half
+        let game = Game::parse(
+            r#"
+            . . . # . . .
+            > > 0 # . . .
+            . . . . . 1 .
+            . . . # . ^ .
+            . . . # . ^ ."#,
+        )
+        .unwrap();
+        let before = game.flood_fill(game.snakes[1].head());
+        let after = game.area_without(1, v2(2, 2));
+        info!("{before} -> {after}");
+        assert_eq!((before, after), (28, 15));
+        assert_eq!(game.best_space_denial(0), Some(Direction::Down));
+
+        // Without opponents there is nothing to deny
+        let mut solo = game.clone();
+        solo.snakes.truncate(1);
+        assert_eq!(solo.best_space_denial(0), None);
+    }
+
+    #[test]
+    fn eval_danger_map() {
+        use super::*;
+        logging();
+
+        // Snake 1 is longer and snake 2 shorter
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . 2 .
+            . . . . . ^ .
+            . 0 . 1 . . .
+            . ^ . ^ . . .
+            . ^ . ^ . . .
+            . ^ . ^ < < ."#,
+        )
+        .unwrap();
+        let danger = game.danger_map();
+        let dangerous: Vec<Vec2D> = (0..7)
+            .flat_map(|y| (0..7).map(move |x| v2(x, y)))
+            .filter(|p| danger[p.x as usize + p.y as usize * 7])
+            .collect();
+        info!("{dangerous:?}");
+        // The neck of snake 1 is no valid move
+        assert_eq!(dangerous, [v2(2, 3), v2(4, 3), v2(3, 4)]);
+        for p in dangerous {
+            assert!(game.head_to_head_risk(0, p));
+        }
+        assert!(!game.head_to_head_risk(0, v2(3, 2)));
+        assert!(!game.head_to_head_risk(0, v2(5, 4)));
+    }
+
+    #[test]
+    fn eval_hazard_forecast() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 0 < < .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        for y in 0..7 {
+            game.grid[v2(0, y)].hazard = true;
+        }
+        game.turn = 20;
+        let forecast = game.hazard_forecast(25);
+        let at = |p: Vec2D| forecast[p.x as usize + p.y as usize * 7];
+        assert_eq!(at(v2(0, 3)), 0);
+        // The next shrink is in 5 turns
+        assert_eq!(at(v2(1, 3)), 5);
+        assert_eq!(at(v2(6, 3)), 5);
+        assert_eq!(at(v2(3, 0)), 5);
+        assert_eq!(at(v2(2, 2)), 30);
+        assert_eq!(at(v2(3, 3)), 55);
+    }
+
+    #[test]
+    fn eval_least_hazard_move() {
+        use super::*;
+        logging();
+
+        // Everything but the left column is hazardous
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        for y in 0..7 {
+            for x in 1..7 {
+                game.grid[v2(x, y)].hazard = true;
+            }
+        }
+        assert_eq!(game.hazard_exit_distance(v2(0, 3)), 0);
+        assert_eq!(game.hazard_exit_distance(v2(1, 3)), 1);
+        assert_eq!(game.hazard_exit_distance(v2(2, 4)), 2);
+        assert_eq!(game.least_hazard_move(0), Direction::Left);
+
+        // Only the right column is safe, but the body is in the way
+        for y in 0..7 {
+            game.grid[v2(0, y)].hazard = true;
+            game.grid[v2(6, y)].hazard = false;
+        }
+        assert_eq!(game.hazard_exit_distance(v2(2, 4)), 4);
+        assert_eq!(game.least_hazard_move(0), Direction::Up);
+    }
+
+    #[test]
+    fn eval_guaranteed_safe_moves() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        // Right might lose a head to head with the longer snake 1
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . 0 . 1 .
+            . ^ . ^ .
+            . ^ . ^ <"#,
+        )
+        .unwrap();
+        assert!(game.move_is_valid(0, Right));
+        assert_eq!(game.guaranteed_safe_moves(0), vec![Up, Left]);
+
+        // Left enters a pocket that is smaller than the body
+        let game = Game::parse(
+            r#"
+            . . . . . .
+            v < < < . .
+            v o . 0 . .
+            > > > ^ . ."#,
+        )
+        .unwrap();
+        assert!(game.move_is_valid(0, Left));
+        assert_eq!(game.guaranteed_safe_moves(0), vec![Up, Right]);
+
+        // The tail of snake 1 stays if it eats
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            > > 0 v .
+            ^ . . v .
+            . . . 1 o
+            . . . . ."#,
+        )
+        .unwrap();
+        assert!(game.move_is_valid(0, Right));
+        assert_eq!(game.guaranteed_safe_moves(0), vec![Up, Down]);
+        game.grid[v2(4, 1)].t = CellT::Free;
+        assert_eq!(game.guaranteed_safe_moves(0), vec![Up, Right, Down]);
+    }
+
+    #[test]
+    fn eval_mobility() {
+        use super::*;
+        logging();
+
+        let open = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . 0 . .
+            . . ^ . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+        let corner = Game::parse(
+            r#"
+            0 < < . .
+            . . . . .
+            . . . . .
+            . . . . .
+            . . . . ."#,
+        )
+        .unwrap();
+        // Three moves with three follow-ups each
+        assert_eq!(open.mobility(0), 3 + 3 * 3);
+        // Down with two follow-ups
+        assert_eq!(corner.mobility(0), 1 + 2);
+
+        let weights = Weights::default();
+        let a = open.evaluate_terms(0, &weights).unwrap();
+        let b = corner.evaluate_terms(0, &weights).unwrap();
+        info!("{a:?} {b:?}");
+        assert_eq!((a.mobility, b.mobility), (1.0, 0.25));
+
+        let weights = Weights {
+            mobility: 0.0,
+            ..Weights::default()
+        };
+        assert_eq!(open.evaluate_terms(0, &weights).unwrap().mobility, 0.0);
+    }
+
+    #[test]
+    fn eval_evaluate_terms() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . 1 .
+            . ^ . ^ o
+            . ^ . ^ ."#,
+        )
+        .unwrap();
+        let weights = Weights {
+            area: 2.0,
+            health: 0.5,
+            ..Weights::default()
+        };
+
+        let terms = game.evaluate_terms(0, &weights).unwrap();
+        info!("{terms:?}");
+        let sum = weights.area * terms.area
+            + weights.health * terms.health
+            + weights.length * terms.length
+            + weights.length_rank * terms.length_rank
+            + weights.longest * terms.longest
+            + weights.tail * terms.tail
+            + weights.mobility * terms.mobility;
+        assert!((sum - game.evaluate_with(0, &weights)).abs() < 1e-12);
+        assert_eq!(terms.total(&weights), game.evaluate_with(0, &weights));
+
+        let moves = game.evaluate_moves(0, &weights);
+        info!("{moves:?}");
+        assert_eq!(moves.len(), game.valid_moves(0).count());
+        for (dir, terms) in moves {
+            let mut next = game.clone();
+            next.step(&[dir, Direction::Up]);
+            let terms = terms.unwrap();
+            assert_eq!(terms.total(&weights), next.evaluate_with(0, &weights));
+        }
+    }
+
+    #[test]
+    fn eval_evaluate_moves_dead() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . 1 .
+            . ^ . ^ o
+            . ^ . ^ ."#,
+        )
+        .unwrap();
+        game.snakes[1].health = 0;
+        game.snakes[1].body.clear();
+        let weights = Weights::default();
+        let moves = game.evaluate_moves(0, &weights);
+        info!("{moves:?}");
+        assert_eq!(moves.len(), 3);
+        assert!(moves.iter().all(|(_, terms)| terms.is_some()));
+
+        // A dead snake has nothing to evaluate
+        game.snakes[0].health = 0;
+        game.snakes[0].body.clear();
+        assert!(game.evaluate_moves(0, &weights).is_empty());
+    }
+
+    #[test]
+    fn eval_unreachable_food() {
+        use super::*;
+        logging();
+
+        // Snake 1 walls off the right side, its stacked tail does not move
+        let game = Game::parse(
+            r#"
+            . . . 1 o
+            . o . ^ .
+            0 . . ^ o
+            ^ . . ^ .
+            ^ . . ^*2 ."#,
+        )
+        .unwrap();
+        info!("{game:?}");
+        assert_eq!(game.unreachable_food(0), [v2(4, 2), v2(4, 4)]);
+        assert_eq!(game.unreachable_food(1), []);
+
+        let mut dead = game.clone();
+        dead.snakes[0].health = 0;
+        assert_eq!(dead.unreachable_food(0).len(), 3);
+    }
+
+    #[test]
+    fn eval_evaluate_length_decay() {
+        use super::*;
+        logging();
+
+        // Longer than half the perimeter
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            0 < < < <
+            > > > > ^
+            ^ < < < <"#,
+        )
+        .unwrap();
+        assert_eq!(game.snakes[0].body.len(), 15);
+
+        // Eating costs one cell of space
+        let mut eaten = game.clone();
+        let tail = eaten.snakes[0].body[0];
+        eaten.snakes[0].body.push_front(tail);
+        eaten.grid[v2(4, 4)].t = CellT::Owned;
+
+        let weights = Weights {
+            length: 2.0,
+            ..Weights::default()
+        };
+        info!(
+            "space: {}, food: {}",
+            game.evaluate_with(0, &weights),
+            eaten.evaluate_with(0, &weights)
+        );
+        assert!(game.evaluate_with(0, &weights) > eaten.evaluate_with(0, &weights));
+
+        // Without decay the food is preferred
+        let weights = Weights {
+            length_decay: 1.0,
+            ..weights
+        };
+        assert!(game.evaluate_with(0, &weights) < eaten.evaluate_with(0, &weights));
+    }
+
+    #[test]
+    fn eval_predicted_head() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . 1 < .
+            0 . . . .
+            ^ . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.predicted_head(0), v2(0, 2));
+        assert_eq!(game.predicted_head(1), v2(1, 2));
+
+        game.wrap = true;
+        assert_eq!(game.predicted_head(0), v2(0, 2));
+
+        // Fresh snakes have no heading
+        let game = Game::parse("0 . .").unwrap();
+        assert_eq!(game.predicted_head(0), v2(0, 0));
+    }
+
+    #[test]
+    fn eval_ranked_moves() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        // The food is in a dead end that is smaller than the snake
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            0 < . . .
+            o ^ < . ."#,
+        )
+        .unwrap();
+        let moves = game.ranked_moves(0);
+        info!("{moves:?}");
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[0].0, Up);
+        assert_eq!(moves[1].0, Down);
+
+        // Moving next to the head of a longer snake is the worst option
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . 0 . 1
+            . . ^ . ^
+            . . . . ^
+            . . . . ^"#,
+        )
+        .unwrap();
+        let moves = game.ranked_moves(0);
+        info!("{moves:?}");
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves[2].0, Right);
+    }
+}
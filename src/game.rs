@@ -1,12 +1,29 @@
 use std::cmp::Reverse;
 use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::{self, Debug};
-
-use owo_colors::{AnsiColors, OwoColorize};
-
-use crate::env::{Battlesnake, Direction, GameRequest, Vec2D, HAZARD_DAMAGE};
-use crate::grid::{Cell, CellT, Grid};
-use crate::util::OrdPair;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::warn;
+use owo_colors::{AnsiColors, DynColors, OwoColorize};
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::env::{Battlesnake, Direction, GameRequest, Origin, Vec2D, HAZARD_DAMAGE};
+use crate::grid::{AStarOptions, Cell, CellT, Grid, Heuristic};
+use crate::png;
+use crate::profile;
+use crate::util::{Fnv1a, OrdPair};
+
+/// Number of snakes above which only the nearest snakes are loaded from a
+/// request by default.
+const CROWDED_SNAKES: usize = 4;
+/// Number of snakes that are loaded from crowded requests by default.
+const MAX_SNAKES: usize = 3;
+/// Health of the snakes at the start of a standard game.
+pub const START_HEALTH: u8 = 100;
 
 /// The outcome of a simulated game.
 /// If the game did not end the outcome is `None`.
@@ -15,23 +32,44 @@ pub enum Outcome {
     None,
     Match,
     Winner(u8),
+    /// A solo game ended with the number of turns the snake survived.
+    Solo(usize),
 }
 
 /// Reduced representation of a snake.
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Snake {
     /// tail to head
     pub body: VecDeque<Vec2D>,
     pub health: u8,
+    /// Display color from the request customizations.
+    pub color: Option<[u8; 3]>,
+    /// Last shout of the snake, if it shouted anything.
+    pub shout: Option<String>,
 }
 impl Snake {
     pub fn new(body: VecDeque<Vec2D>, health: u8) -> Self {
-        Self { body, health }
+        Self {
+            body,
+            health,
+            color: None,
+            shout: None,
+        }
     }
 
+    /// Dead snakes (no health or no body) are loaded without a body.
     #[must_use]
     pub fn from(snake: &Battlesnake) -> Self {
-        Self::new(snake.body.iter().cloned().rev().collect(), snake.health)
+        let (body, health) = if snake.health == 0 || snake.body.is_empty() {
+            (VecDeque::new(), 0)
+        } else {
+            (snake.body.iter().cloned().rev().collect(), snake.health)
+        };
+        Self {
+            color: snake.customizations.rgb(),
+            shout: (!snake.shout.is_empty()).then(|| snake.shout.clone()),
+            ..Self::new(body, health)
+        }
     }
 
     pub fn alive(&self) -> bool {
@@ -41,19 +79,95 @@ impl Snake {
     pub fn head(&self) -> Vec2D {
         *self.body.back().unwrap()
     }
+
+    /// Manhattan distance of the nearest body cell to `p`.
+    pub fn body_distance(&self, p: Vec2D) -> u64 {
+        self.body
+            .iter()
+            .map(|&b| (b - p).manhattan())
+            .min()
+            .unwrap_or_default()
+    }
+}
+
+impl Clone for Snake {
+    fn clone(&self) -> Self {
+        Self {
+            body: self.body.clone(),
+            health: self.health,
+            color: self.color,
+            shout: self.shout.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.body.clone_from(&source.body);
+        self.health = source.health;
+        self.color = source.color;
+        self.shout.clone_from(&source.shout);
+    }
+}
+
+/// Maximum number of combinations of [`Game::joint_moves`].
+pub const MAX_JOINT_MOVES: usize = 1024;
+
+/// Outcome of a head to head between snakes of equal length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HeadToHeadPolicy {
+    /// Both snakes die, like in the standard rules.
+    #[default]
+    BothDie,
+    /// The snake with more health survives, both die on equal health.
+    Health,
+    /// Both snakes survive and share the head cell.
+    BothSurvive,
+}
+
+/// Reason why a snake died in a [`Game::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum DeathCause {
+    /// Moved out of the board.
+    Wall,
+    /// Moved into the body of the snake with the given index.
+    Body(u8),
+    /// Moved into its own body.
+    SelfCollision,
+    /// Lost a head to head against the snake with the given index.
+    HeadToHead(u8),
+    /// Ran out of health.
+    Starvation,
 }
 
 /// Game represents holds the complete game state.
 /// This also provides methods to execute moves and evaluate their outcome.
-#[derive(Clone)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Game {
     pub turn: usize,
+    /// If the edges of the board are connected (wrapped ruleset).
+    #[serde(default)]
+    pub wrap: bool,
+    /// Rule for head to heads of equal length.
+    #[serde(default)]
+    pub head_to_head: HeadToHeadPolicy,
     pub grid: Grid,
     /// All snakes. Dead ones have health = 0 and no body.
     /// The ids have to be the same as the indices!
     pub snakes: Vec<Snake>,
 }
 
+impl Clone for Game {
+    fn clone(&self) -> Self {
+        profile::count(|c| c.game_clones += 1);
+        Self {
+            turn: self.turn,
+            wrap: self.wrap,
+            head_to_head: self.head_to_head,
+            grid: self.grid.clone(),
+            snakes: self.snakes.clone(),
+        }
+    }
+}
+
 impl Game {
     /// Creates the game state.
     #[must_use]
@@ -73,62 +187,176 @@ impl Game {
             grid.add_snake(snake.body.iter().copied());
         }
 
-        Self { turn, snakes, grid }
+        Self {
+            turn,
+            wrap: false,
+            head_to_head: HeadToHeadPolicy::default(),
+            snakes,
+            grid,
+        }
+    }
+
+    /// Creates a game with `n` snakes (at most 8) at the standard battlesnake
+    /// starting positions and the standard initial food.
+    ///
+    /// The snakes start in the corners and then on the middle of the edges,
+    /// with bodies of three stacked cells.
+    /// Each snake gets one food two moves away that is not closer to the
+    /// center, and one food is placed in the center.
+    /// The snakes start with `health`, which is [`START_HEALTH`] in the
+    /// standard rules.
+    #[must_use]
+    pub fn standard_start(width: usize, height: usize, n: usize, health: u8) -> Self {
+        assert!(n <= 8, "Only up to 8 start positions");
+        let (w, h) = (width as i16, height as i16);
+        let (mn, mx_x, mx_y) = (1, w - 2, h - 2);
+        let (md_x, md_y) = ((w - 1) / 2, (h - 1) / 2);
+        let positions = [
+            Vec2D::new(mn, mn),
+            Vec2D::new(mx_x, mx_y),
+            Vec2D::new(mn, mx_y),
+            Vec2D::new(mx_x, mn),
+            Vec2D::new(mn, md_y),
+            Vec2D::new(mx_x, md_y),
+            Vec2D::new(md_x, mn),
+            Vec2D::new(md_x, mx_y),
+        ];
+
+        let snakes = positions[..n]
+            .iter()
+            .map(|&p| Snake::new([p; 3].into(), health))
+            .collect();
+
+        let center = Vec2D::new(md_x, md_y);
+        let mut food = Vec::with_capacity(n + 1);
+        for &head in &positions[..n] {
+            let away = |p: Vec2D| {
+                (p.x < head.x && head.x < center.x)
+                    || (center.x < head.x && head.x < p.x)
+                    || (p.y < head.y && head.y < center.y)
+                    || (center.y < head.y && head.y < p.y)
+            };
+            let corner = |p: Vec2D| (p.x == 0 || p.x == w - 1) && (p.y == 0 || p.y == h - 1);
+            if let Some(p) = [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+                .into_iter()
+                .map(|(x, y)| head + Vec2D::new(x, y))
+                .find(|&p| {
+                    p.within(width, height)
+                        && away(p)
+                        && !corner(p)
+                        && !positions[..n].contains(&p)
+                        && !food.contains(&p)
+                })
+            {
+                food.push(p);
+            }
+        }
+        if !positions[..n].contains(&center) {
+            food.push(center);
+        }
+
+        Self::new(0, width, height, snakes, &food, &[])
     }
 
     /// Loads the game state from the provided request.
+    ///
+    /// If there are more than four snakes, only you and the nearest two
+    /// opponents are considered.
     #[must_use]
     pub fn from_request(request: &GameRequest) -> Self {
-        let mut snakes = Vec::with_capacity(4);
+        if request.board.snakes.len() > CROWDED_SNAKES {
+            Self::from_request_capped(request, MAX_SNAKES)
+        } else {
+            Self::from_request_full(request)
+        }
+    }
+
+    /// Loads the game state from the provided request with all snakes.
+    #[must_use]
+    pub fn from_request_full(request: &GameRequest) -> Self {
+        Self::from_request_capped(request, usize::MAX)
+    }
+
+    /// Loads the game state from the provided request with at most
+    /// `max_snakes` snakes, you and the nearest opponents.
+    #[must_use]
+    pub fn from_request_capped(request: &GameRequest, max_snakes: usize) -> Self {
+        let opponents = Self::opponents(request);
+        let mut snakes = Vec::with_capacity((opponents.len() + 1).min(max_snakes));
         snakes.push(Snake::from(&request.you));
 
-        // Only look at the nearest four snakes
-        if request.board.snakes.len() > 4 {
+        if opponents.len() >= max_snakes {
+            // If you are dead, the distances do not matter
+            let you = snakes[0].body.back().copied().unwrap_or_default();
             let mut queue = BinaryHeap::new();
-            for snake in request
-                .board
-                .snakes
-                .iter()
-                .filter(|s| s.id != request.you.id)
-                .map(Snake::from)
-            {
-                let body_dist = snake
-                    .body
-                    .iter()
-                    .map(|&p| (p - snakes[0].head()).manhattan())
-                    .min()
-                    .unwrap_or_default();
+            for snake in opponents.into_iter().map(Snake::from) {
+                let body_dist = snake.body_distance(you);
                 queue.push(OrdPair(Reverse(body_dist), snake));
             }
 
-            for _ in 1..3 {
+            for _ in 1..max_snakes {
                 if let Some(OrdPair(_, snake)) = queue.pop() {
                     snakes.push(snake);
                 }
             }
         } else {
-            snakes.extend(
-                request
-                    .board
-                    .snakes
-                    .iter()
-                    .filter(|s| s.id != request.you.id)
-                    .map(Snake::from),
-            );
+            snakes.extend(opponents.into_iter().map(Snake::from));
         }
-        Self::new(
-            request.turn,
-            request.board.width,
-            request.board.height,
-            snakes,
-            &request.board.food,
-            &request.board.hazards,
-        )
+        let mut game = Self {
+            wrap: request.game.ruleset.name == "wrapped",
+            ..Self::new(
+                request.turn,
+                request.board.width,
+                request.board.height,
+                snakes,
+                &request.board.food,
+                &request.board.hazards,
+            )
+        };
+        game.grid.add_hazard_zones(&request.board.hazard_zones);
+        game.grid.add_walls(&request.board.walls);
+        game
+    }
+
+    /// Returns the snakes of the board except you.
+    ///
+    /// The agents identify snakes by their index, so every id is only loaded
+    /// once: the first snake with an id is kept and duplicates are dropped
+    /// with a warning.
+    /// You are loaded from `request.you`, which the board lists once as well.
+    fn opponents(request: &GameRequest) -> Vec<&Battlesnake> {
+        let mut opponents: Vec<&Battlesnake> = Vec::with_capacity(request.board.snakes.len());
+        let mut you_seen = false;
+        for snake in &request.board.snakes {
+            if snake.id == request.you.id {
+                if you_seen {
+                    warn!("duplicate snake id {:?} in {}", snake.id, request.game.id);
+                }
+                you_seen = true;
+                continue;
+            }
+            if opponents.iter().any(|s| s.id == snake.id) {
+                warn!("duplicate snake id {:?} in {}", snake.id, request.game.id);
+            } else {
+                opponents.push(snake);
+            }
+        }
+        opponents
     }
 
     /// Returns if the game has ended and which snake is the winner or if the
     /// game was a match.
+    ///
+    /// Solo games with a single snake end when it dies.
     pub fn outcome(&self) -> Outcome {
+        if let [snake] = &self.snakes[..] {
+            return if snake.alive() {
+                Outcome::None
+            } else {
+                Outcome::Solo(self.turn)
+            };
+        }
+
         let mut living_snakes = 0;
         let mut survivor = 0;
         for (i, snake) in self.snakes.iter().enumerate() {
@@ -144,6 +372,184 @@ impl Game {
         }
     }
 
+    /// Copies this game into `dst`, reusing its allocations if possible.
+    ///
+    /// This is cheaper than `clone` if the same game is copied repeatedly.
+    pub fn clone_into(&self, dst: &mut Game) {
+        profile::count(|c| c.game_clones += 1);
+        dst.turn = self.turn;
+        dst.wrap = self.wrap;
+        dst.head_to_head = self.head_to_head;
+        dst.grid.clone_from(&self.grid);
+        dst.snakes.clone_from(&self.snakes);
+    }
+
+    /// Returns a hash of the current position (grid and snake bodies).
+    ///
+    /// The turn and the health of the snakes are not part of the position,
+    /// which allows detecting repetitions.
+    pub fn encode(&self) -> u128 {
+        let mut hasher = Fnv1a::new();
+        hasher.write(&(self.grid.width as u16).to_le_bytes());
+        hasher.write(&(self.grid.height as u16).to_le_bytes());
+        for cell in &self.grid.cells {
+            hasher.write(&[cell.t as u8 | (cell.hazard as u8) << 4]);
+        }
+        for snake in &self.snakes {
+            hasher.write(&(snake.body.len() as u16).to_le_bytes());
+            for p in &snake.body {
+                hasher.write(&p.x.to_le_bytes());
+                hasher.write(&p.y.to_le_bytes());
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Returns a short hex fingerprint of the position (the first 8 hex
+    /// digits of [`Game::encode`]) for correlating logs with boards.
+    pub fn fingerprint(&self) -> String {
+        format!("{:032x}", self.encode())[..8].to_string()
+    }
+
+    /// Returns if the current position already occurred twice in the `recent`
+    /// history of [`Game::encode`]s, so the snakes are moving in circles.
+    ///
+    /// Self-play can declare such games a draw or perturb them.
+    pub fn is_stalemate(&self, recent: &[u128]) -> bool {
+        let position = self.encode();
+        recent.iter().filter(|&&p| p == position).count() >= 2
+    }
+
+    /// Returns the `n` living opponents that are nearest to the head of the
+    /// snake (by [`Snake::body_distance`]), nearest first.
+    ///
+    /// Searches can limit their branching to these opponents, like
+    /// [`Game::from_request`] limits the number of snakes.
+    pub fn nearest_opponents(&self, snake: u8, n: usize) -> Vec<u8> {
+        if !self.snake_is_alive(snake) {
+            return Vec::new();
+        }
+        let head = self.snakes[snake as usize].head();
+        let mut opponents: Vec<u8> = (0..self.snakes.len() as u8)
+            .filter(|&i| i != snake && self.snake_is_alive(i))
+            .collect();
+        opponents.sort_by_key(|&i| (self.snakes[i as usize].body_distance(head), i));
+        opponents.truncate(n);
+        opponents
+    }
+
+    /// Returns the neighbor of `p` in the given direction.
+    /// On wrapped boards, moves over the edges continue on the opposite side.
+    #[inline]
+    pub fn neighbor(&self, p: Vec2D, dir: Direction) -> Vec2D {
+        let p = p.apply(dir);
+        if self.wrap {
+            self.grid.wrap(p)
+        } else {
+            p
+        }
+    }
+
+    /// Returns the minimal [`Game::encode`] of all rotations and reflections
+    /// of the board.
+    ///
+    /// Equivalent positions have the same canonical encoding.
+    /// The snakes keep their order, so you remain snake 0.
+    pub fn canonical_encode(&self) -> u128 {
+        (0..8).map(|t| self.transformed(t).encode()).min().unwrap()
+    }
+
+    /// Returns the direction from the neck to the head of the snake, or
+    /// `None` if it has no heading (e.g. on its first turn).
+    pub fn heading(&self, snake: u8) -> Option<Direction> {
+        let body = &self.snakes.get(snake as usize)?.body;
+        let head = *body.back()?;
+        let neck = body[body.len().checked_sub(2)?];
+        Direction::all()
+            .into_iter()
+            .find(|&d| neck != head && self.neighbor(neck, d) == head)
+    }
+
+    /// Returns the board rotated so that the heading of the snake points up,
+    /// e.g. for policies that expect head-relative features.
+    ///
+    /// Snakes without a heading are not rotated.
+    /// Moves in the view are mapped back with [`Game::oriented_move`].
+    pub fn oriented_view(&self, snake: u8) -> Game {
+        self.transformed(self.orientation(snake))
+    }
+
+    /// Maps a move in the [`Game::oriented_view`] of the snake back to the
+    /// absolute direction.
+    pub fn oriented_move(&self, snake: u8, dir: Direction) -> Direction {
+        Direction::from((dir as u8 + 4 - self.orientation(snake)) % 4)
+    }
+
+    /// Number of clockwise rotations that turn the heading of the snake up.
+    fn orientation(&self, snake: u8) -> u8 {
+        self.heading(snake).map_or(0, |d| (4 - d as u8) % 4)
+    }
+
+    /// Returns the game mirrored at the vertical axis, so that left and right
+    /// are swapped (see [`Direction::reflect_x`]).
+    ///
+    /// Like the other transforms, this keeps the order of the snakes and is
+    /// useful to augment training data.
+    pub fn reflect_x(&self) -> Game {
+        self.transformed(4)
+    }
+
+    /// Returns the game mirrored at the horizontal axis, so that up and down
+    /// are swapped (see [`Direction::reflect_y`]).
+    pub fn reflect_y(&self) -> Game {
+        self.transformed(6)
+    }
+
+    /// Returns the game rotated clockwise by 90 degrees
+    /// (see [`Direction::rotate90`]).
+    pub fn rotate90(&self) -> Game {
+        self.transformed(1)
+    }
+
+    /// Returns the game reflected horizontally if `t >= 4` and then rotated
+    /// `t % 4` times clockwise.
+    fn transformed(&self, t: u8) -> Game {
+        let (w, h) = (self.grid.width as i16, self.grid.height as i16);
+        let (width, height) = if t.is_multiple_of(2) { (w, h) } else { (h, w) };
+        let transform = |mut p: Vec2D| {
+            if t >= 4 {
+                p.x = w - 1 - p.x;
+            }
+            let (mut w, mut h) = (w, h);
+            for _ in 0..t % 4 {
+                p = Vec2D::new(p.y, w - 1 - p.x);
+                (w, h) = (h, w);
+            }
+            p
+        };
+
+        let mut grid = Grid::new(width as _, height as _);
+        for y in 0..h {
+            for x in 0..w {
+                let p = Vec2D::new(x, y);
+                grid[transform(p)] = self.grid[p];
+            }
+        }
+        let mut snakes = self.snakes.clone();
+        for snake in &mut snakes {
+            for p in &mut snake.body {
+                *p = transform(*p);
+            }
+        }
+        Game {
+            turn: self.turn,
+            wrap: self.wrap,
+            head_to_head: self.head_to_head,
+            grid,
+            snakes,
+        }
+    }
+
     /// Returns if a snake is alive.
     pub fn snake_is_alive(&self, snake: u8) -> bool {
         snake < self.snakes.len() as u8 && self.snakes[snake as usize].alive()
@@ -152,7 +558,7 @@ impl Game {
     /// Returns all valid moves that do not immediately kill the snake.
     /// Head to head collisions are not considered.
     #[inline]
-    pub fn valid_moves(&self, snake: u8) -> ValidMoves {
+    pub fn valid_moves(&self, snake: u8) -> ValidMoves<'_> {
         if self.snake_is_alive(snake) {
             ValidMoves::new(self, &self.snakes[snake as usize])
         } else {
@@ -160,6 +566,39 @@ impl Game {
         }
     }
 
+    /// Returns every combination of the valid moves of the snakes, which are
+    /// the branches of a simultaneous move search.
+    ///
+    /// Dead snakes and snakes without valid moves contribute [`Direction::Up`].
+    /// At most [`MAX_JOINT_MOVES`] combinations are returned, varying the
+    /// moves of the last snakes first.
+    pub fn joint_moves(&self) -> impl Iterator<Item = Vec<Direction>> {
+        let options: Vec<Vec<Direction>> = (0..self.snakes.len() as u8)
+            .map(|i| {
+                let moves: Vec<Direction> = self.valid_moves(i).collect();
+                if moves.is_empty() {
+                    vec![Direction::Up]
+                } else {
+                    moves
+                }
+            })
+            .collect();
+        let count = options
+            .iter()
+            .try_fold(1usize, |n, o| n.checked_mul(o.len()))
+            .unwrap_or(usize::MAX)
+            .min(MAX_JOINT_MOVES);
+
+        (0..count).map(move |mut i| {
+            let mut moves = vec![Direction::Up; options.len()];
+            for (m, o) in moves.iter_mut().zip(&options).rev() {
+                *m = o[i % o.len()];
+                i /= o.len();
+            }
+            moves
+        })
+    }
+
     /// Returns if a move will not immediately kill the snake.
     /// Head to head collisions are not considered.
     pub fn move_is_valid(&self, snake: u8, dir: Direction) -> bool {
@@ -168,21 +607,114 @@ impl Game {
 
     #[inline]
     fn snake_move_is_valid(&self, snake: &Snake, dir: Direction) -> bool {
-        let p = snake.head().apply(dir);
-        // Free or occupied by tail (free in the next turn)
+        self.is_walkable(self.neighbor(snake.head(), dir))
+    }
+
+    /// Returns if a snake can move onto `p` in the next turn without
+    /// colliding (head to heads are not considered).
+    ///
+    /// These are all free cells and the tails that move in the next turn.
+    /// Stacked tails (after spawning or eating) do not move.
+    #[inline]
+    pub fn is_walkable(&self, p: Vec2D) -> bool {
         self.grid.has(p)
-            && (self.grid[p].t != CellT::Owned
-                || self
-                    .snakes
-                    .iter()
-                    .filter(|s| s.alive())
-                    .any(|s| p == s.body[0] && p != s.body[1]))
+            && (!self.grid[p].t.is_obstacle()
+                || self.grid[p].t == CellT::Owned
+                    && self
+                        .snakes
+                        .iter()
+                        .filter(|s| s.alive() && s.body.len() > 1)
+                        .any(|s| p == s.body[0] && p != s.body[1]))
+    }
+
+    /// A* search on the grid (see [`Grid::a_star`]) over the
+    /// [`Game::is_walkable`] cells.
+    #[must_use]
+    pub fn a_star(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        first_move_heuristic: &[f64; 4],
+        heuristic: Heuristic,
+    ) -> Option<Vec<Vec2D>> {
+        self.grid.a_star_with(
+            start,
+            target,
+            first_move_heuristic,
+            heuristic,
+            AStarOptions::default(),
+            |p| self.is_walkable(p),
+        )
+    }
+
+    /// Like [`Game::a_star`], but the search expands at most `max_nodes`
+    /// nodes and might return a partial path toward the target
+    /// (see [`Grid::a_star_with`]).
+    #[must_use]
+    pub fn a_star_budget(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        heuristic: Heuristic,
+        max_nodes: usize,
+    ) -> Option<Vec<Vec2D>> {
+        self.grid.a_star_with(
+            start,
+            target,
+            &[0.0; 4],
+            heuristic,
+            AStarOptions {
+                max_nodes: Some(max_nodes),
+                ..AStarOptions::default()
+            },
+            |p| self.is_walkable(p),
+        )
+    }
+
+    /// Like [`Game::a_star`], but moves onto food are cheaper by `food_bias`
+    /// (between 0 and 1), which prefers paths over food without detours.
+    #[must_use]
+    pub fn a_star_via_food(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        heuristic: Heuristic,
+        food_bias: f64,
+    ) -> Option<Vec<Vec2D>> {
+        self.grid.a_star_with(
+            start,
+            target,
+            &[0.0; 4],
+            heuristic,
+            AStarOptions {
+                food_bias,
+                ..AStarOptions::default()
+            },
+            |p| self.is_walkable(p),
+        )
     }
 
     /// Executed the provided moves for each living agent.
     /// This method also checks for eating and collision with walls or other snakes.
     pub fn step(&mut self, moves: &[Direction]) {
+        self.step_inner(moves, None)
+    }
+
+    /// Like [`Game::step`], but returns the snakes that died during the turn
+    /// with the cause of their death.
+    pub fn step_deaths(&mut self, moves: &[Direction]) -> Vec<(u8, DeathCause)> {
+        let mut deaths = Vec::new();
+        self.step_inner(moves, Some(&mut deaths));
+        deaths
+    }
+
+    fn step_inner(&mut self, moves: &[Direction], mut deaths: Option<&mut Vec<(u8, DeathCause)>>) {
         assert!(moves.len() >= self.snakes.len());
+        let mut report = |id: usize, cause: DeathCause| {
+            if let Some(deaths) = deaths.as_mut() {
+                deaths.push((id as u8, cause));
+            }
+        };
 
         // Pop tail
         for snake in &mut self.snakes {
@@ -199,21 +731,24 @@ impl Game {
         for (id, snake) in self.snakes.iter_mut().enumerate() {
             if snake.alive() {
                 let dir = moves[id];
-                let head = snake.head().apply(dir);
+                let mut head = snake.head().apply(dir);
+                if self.wrap {
+                    head = self.grid.wrap(head);
+                }
 
                 if !self.grid.has(head) {
+                    report(id, DeathCause::Wall);
                     snake.health = 0;
+                    for &p in &snake.body {
+                        self.grid[p].t = CellT::Free;
+                    }
+                    snake.body.clear();
                     continue;
                 }
 
                 snake.body.push_back(head);
 
                 let g_cell = self.grid[head];
-                if g_cell.t == CellT::Owned {
-                    snake.health = 0;
-                    continue;
-                }
-
                 snake.health = if g_cell.t == CellT::Food {
                     snake.body.push_front(*snake.body.front().unwrap());
                     100
@@ -222,7 +757,46 @@ impl Game {
                         .health
                         .saturating_sub(if g_cell.hazard { HAZARD_DAMAGE } else { 1 })
                 };
+
+                // Starved snakes are removed before the collisions are checked
+                // so that they are no obstacles for the other snakes.
+                if !snake.alive() {
+                    report(id, DeathCause::Starvation);
+                    Self::remove_snake(&mut self.grid, snake);
+                }
+            }
+        }
+
+        // Check body collisions, including the own body.
+        // Tails were freed above unless they are stacked after eating.
+        let mut collided = Vec::new();
+        for (id, snake) in self.snakes.iter().enumerate() {
+            if snake.alive() && self.grid[snake.head()].t.is_obstacle() {
+                collided.push(id);
+            }
+        }
+        for &id in &collided {
+            let head = self.snakes[id].head();
+            if self.grid[head].t == CellT::Wall {
+                report(id, DeathCause::Wall);
+                continue;
             }
+            // The new heads are not yet owned cells
+            let owner = self
+                .snakes
+                .iter()
+                .position(|s| s.alive() && s.body.iter().rev().skip(1).any(|&p| p == head));
+            report(
+                id,
+                match owner {
+                    Some(owner) if owner == id => DeathCause::SelfCollision,
+                    Some(owner) => DeathCause::Body(owner as _),
+                    None => DeathCause::SelfCollision,
+                },
+            );
+        }
+        for id in collided {
+            self.snakes[id].health = 0;
         }
 
         // Check head to head
@@ -232,13 +806,27 @@ impl Game {
                 for j in i + 1..self.snakes.len() {
                     if self.snakes[j].alive() && self.snakes[i].head() == self.snakes[j].head() {
                         use std::cmp::Ordering;
-                        match self.snakes[i].body.len().cmp(&self.snakes[j].body.len()) {
-                            Ordering::Less => self.snakes[i].health = 0,
-                            Ordering::Greater => self.snakes[j].health = 0,
-                            Ordering::Equal => {
-                                self.snakes[i].health = 0;
-                                self.snakes[j].health = 0;
-                            }
+                        let (i_dies, j_dies) =
+                            match self.snakes[i].body.len().cmp(&self.snakes[j].body.len()) {
+                                Ordering::Less => (true, false),
+                                Ordering::Greater => (false, true),
+                                Ordering::Equal => match self.head_to_head {
+                                    HeadToHeadPolicy::BothDie => (true, true),
+                                    HeadToHeadPolicy::Health => {
+                                        let (hi, hj) =
+                                            (self.snakes[i].health, self.snakes[j].health);
+                                        (hi <= hj, hj <= hi)
+                                    }
+                                    HeadToHeadPolicy::BothSurvive => (false, false),
+                                },
+                            };
+                        if i_dies {
+                            report(i, DeathCause::HeadToHead(j as _));
+                            self.snakes[i].health = 0;
+                        }
+                        if j_dies {
+                            report(j, DeathCause::HeadToHead(i as _));
+                            self.snakes[j].health = 0;
                         }
                     }
                 }
@@ -246,56 +834,94 @@ impl Game {
         }
 
         // Clear died snakes
-        let grid = &mut self.grid;
         for snake in &mut self.snakes {
             if snake.alive() {
-                let head_cell = &mut grid[snake.head()];
-                head_cell.t = CellT::Owned;
+                self.grid[snake.head()].t = CellT::Owned;
             } else if !snake.body.is_empty() {
-                for &p in &snake.body {
-                    grid[p].t = CellT::Free;
-                }
-                snake.body.clear();
+                Self::remove_snake(&mut self.grid, snake);
             }
         }
 
         self.turn += 1;
     }
+
+    /// Like [`Game::step`], but returns all cells that changed during the
+    /// turn as `(position, old, new)`.
+    pub fn step_tracked(&mut self, moves: &[Direction]) -> Vec<(Vec2D, Cell, Cell)> {
+        let old = self.grid.cells.clone();
+        self.step(moves);
+
+        let width = self.grid.width;
+        old.into_iter()
+            .zip(&self.grid.cells)
+            .enumerate()
+            .filter(|(_, (old, new))| old != *new)
+            .map(|(i, (old, &new))| (Vec2D::new((i % width) as _, (i / width) as _), old, new))
+            .collect()
+    }
+
+    /// Removes a snake that died while moving from the grid.
+    ///
+    /// Its new head has not been added to the grid yet and might be owned by
+    /// another snake, so it is only cleared if the snake ate food there.
+    fn remove_snake(grid: &mut Grid, snake: &mut Snake) {
+        if let Some(head) = snake.body.pop_back() {
+            if grid[head].t == CellT::Food {
+                grid[head].t = CellT::Free;
+            }
+        }
+        for &p in &snake.body {
+            grid[p].t = CellT::Free;
+        }
+        snake.body.clear();
+    }
 }
 
 impl Game {
     /// Parses textual human readable board representation used in test.
+    ///
+    /// Cells are `.` (free), `o` (food), `#` (walls), `0`-`9` (heads) or `^`,
+    /// `>`, `v`, `<` (body segments pointing to the next segment toward the
+    /// head).
+    /// Snake cells can have a stack count (`0*3`, `^*2`), otherwise bodies
+    /// are stacked up to three cells at their tail.
     #[must_use]
     pub fn parse(txt: &str) -> Option<Self> {
         #[derive(PartialEq)]
         enum RawCell {
             Free,
             Food,
+            Wall,
             Head(u8),
             Body(Direction),
         }
 
         let txt = txt.trim();
-        let raw_cells: Vec<RawCell> = txt
+        let (raw_cells, counts): (Vec<RawCell>, Vec<Option<usize>>) = txt
             .lines()
             .rev()
             .flat_map(|l| {
                 l.split_whitespace().flat_map(|s| {
-                    s.chars().next().map(|c| match c {
-                        'o' => RawCell::Food,
-                        '0'..='9' => RawCell::Head(c.to_digit(10).unwrap() as u8),
-                        '^' => RawCell::Body(Direction::Up),
-                        '>' => RawCell::Body(Direction::Right),
-                        'v' => RawCell::Body(Direction::Down),
-                        '<' => RawCell::Body(Direction::Left),
-                        _ => RawCell::Free,
+                    let count = s.split_once('*').and_then(|(_, n)| n.parse().ok());
+                    s.chars().next().map(|c| {
+                        let cell = match c {
+                            'o' => RawCell::Food,
+                            '#' => RawCell::Wall,
+                            '0'..='9' => RawCell::Head(c.to_digit(10).unwrap() as u8),
+                            '^' => RawCell::Body(Direction::Up),
+                            '>' => RawCell::Body(Direction::Right),
+                            'v' => RawCell::Body(Direction::Down),
+                            '<' => RawCell::Body(Direction::Left),
+                            _ => RawCell::Free,
+                        };
+                        (cell, count)
                     })
                 })
             })
-            .collect();
+            .unzip();
         let height = txt.lines().count();
 
-        if raw_cells.len() % height != 0 {
+        if !raw_cells.len().is_multiple_of(height) {
             return None;
         }
         let width = raw_cells.len() / height;
@@ -305,6 +931,7 @@ impl Game {
             grid[Vec2D::new((i % width) as _, (i / width) as _)] = match cell {
                 RawCell::Free => Cell::new(CellT::Free, false),
                 RawCell::Food => Cell::new(CellT::Food, false),
+                RawCell::Wall => Cell::new(CellT::Wall, false),
                 _ => Cell::new(CellT::Owned, false),
             }
         }
@@ -313,22 +940,33 @@ impl Game {
         for i in 0..=9 {
             if let Some(p) = raw_cells.iter().position(|c| *c == RawCell::Head(i)) {
                 let mut p = Vec2D::new((p % width) as _, (p / width) as _);
+                let index = |p: Vec2D| (p.x + p.y * width as i16) as usize;
                 let mut body = VecDeque::new();
-                body.push_front(p);
+                let mut explicit = false;
+                let mut push = |body: &mut VecDeque<Vec2D>, p: Vec2D| {
+                    let count = counts[index(p)];
+                    explicit |= count.is_some();
+                    for _ in 0..count.unwrap_or(1) {
+                        body.push_front(p);
+                    }
+                };
+                push(&mut body, p);
                 while let Some(next) = Direction::all().into_iter().find_map(|d| {
                     let next = p.apply(d);
                     (next.within(width, height)
-                        && raw_cells[(next.x + next.y * width as i16) as usize]
-                            == RawCell::Body(d.invert()))
+                        && raw_cells[index(next)] == RawCell::Body(d.invert()))
                     .then_some(next)
                 }) {
                     p = next;
-                    body.push_front(p);
+                    push(&mut body, p);
                 }
-                while body.len() < 3 {
+                if body.is_empty() {
+                    return None;
+                }
+                while !explicit && body.len() < 3 {
                     body.push_front(body[0]);
                 }
-                snakes.push(Snake::new(body, 100));
+                snakes.push(Snake::new(body, START_HEALTH));
             } else {
                 break;
             }
@@ -336,111 +974,453 @@ impl Game {
 
         Some(Self {
             turn: 0,
+            wrap: false,
+            head_to_head: HeadToHeadPolicy::default(),
             grid,
             snakes,
         })
     }
 }
 
-impl Debug for Game {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        #[derive(Clone, Copy, PartialEq, Eq)]
-        enum FmtCell {
-            Free,
-            Food,
-            Tail(Direction, u8),
-            Head(u8),
-        }
-        fn id_color(id: u8) -> AnsiColors {
-            match id {
-                0 => AnsiColors::Green,
-                1 => AnsiColors::Yellow,
-                2 => AnsiColors::Blue,
-                3 => AnsiColors::Magenta,
-                _ => AnsiColors::Cyan,
-            }
-        }
-        impl Debug for FmtCell {
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                match *self {
-                    FmtCell::Free => write!(f, "."),
-                    FmtCell::Food => write!(f, "{}", "o".red()),
-                    FmtCell::Tail(dir, id) => match dir {
-                        Direction::Up => write!(f, "{}", "^".color(id_color(id))),
-                        Direction::Right => write!(f, "{}", ">".color(id_color(id))),
-                        Direction::Down => write!(f, "{}", "v".color(id_color(id))),
-                        Direction::Left => write!(f, "{}", "<".color(id_color(id))),
-                    },
-                    FmtCell::Head(id) => write!(f, "{}", id.color(id_color(id))),
+impl Game {
+    /// Returns the board representation that is read by [`Game::parse`].
+    ///
+    /// Stacked snake cells are written with their count (e.g. `0*3`).
+    /// Hazards, health and the turn are not part of this representation.
+    pub fn to_parse_string(&self) -> String {
+        let width = self.grid.width;
+        let mut cells: Vec<String> = self
+            .grid
+            .cells
+            .iter()
+            .map(|c| match c.t {
+                CellT::Food => "o",
+                CellT::Wall => "#",
+                _ => ".",
+            })
+            .map(str::to_string)
+            .collect();
+        for (id, snake) in self.snakes.iter().enumerate().take(10) {
+            let body = &snake.body;
+            let mut i = 0;
+            while i < body.len() {
+                let p = body[i];
+                let mut count = 1;
+                while i + count < body.len() && body[i + count] == p {
+                    count += 1;
                 }
-            }
-        }
-
-        let mut cells = vec![(FmtCell::Free, false); self.grid.width * self.grid.height];
-
-        for y in 0..self.grid.height {
-            for x in 0..self.grid.width {
-                let cell = &mut cells[y * self.grid.width + x];
-                let g_cell = self.grid[Vec2D::new(x as _, y as _)];
-                cell.0 = if g_cell.t == CellT::Food {
-                    FmtCell::Food
+                let glyph = match body.get(i + count) {
+                    Some(&next) => match Direction::from(next - p) {
+                        Direction::Up => '^',
+                        Direction::Right => '>',
+                        Direction::Down => 'v',
+                        Direction::Left => '<',
+                    },
+                    None => char::from_digit(id as _, 10).unwrap(),
+                };
+                cells[p.x as usize + p.y as usize * width] = if count > 1 {
+                    format!("{glyph}*{count}")
                 } else {
-                    FmtCell::Free
+                    glyph.to_string()
                 };
-                cell.1 = g_cell.hazard;
+                i += count;
             }
         }
 
-        for (id, snake) in self.snakes.iter().enumerate() {
-            if !snake.alive() || snake.body.is_empty() {
-                continue;
-            }
+        let mut txt = String::new();
+        for y in (0..self.grid.height).rev() {
+            txt.push_str(&cells[y * width..(y + 1) * width].join(" "));
+            txt.push('\n');
+        }
+        txt
+    }
+}
 
-            let mut last_body = *snake.body.front().unwrap();
+/// A frame of a replay.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ReplayFrame {
+    game: Game,
+    /// Moves to the next frame, `None` for dead snakes.
+    #[serde(default)]
+    moves: Vec<Option<Direction>>,
+}
 
-            for next_body in snake.body.iter().skip(1).copied() {
-                cells[last_body.y as usize * self.grid.width + last_body.x as usize].0 =
-                    FmtCell::Tail(Direction::from(next_body - last_body), id as _);
+impl Game {
+    /// Serializes the game state to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
 
-                last_body = next_body;
-            }
+    /// Deserializes a game state from JSON.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
 
-            cells[last_body.y as usize * self.grid.width + last_body.x as usize].0 =
-                FmtCell::Head(id as _);
+    /// Serializes the game state to JSON with coordinates and grid rows in
+    /// the given convention.
+    pub fn to_json_origin(&self, origin: Origin) -> String {
+        match origin {
+            Origin::BottomLeft => self.to_json(),
+            Origin::TopLeft => self.flipped().to_json(),
         }
+    }
 
-        writeln!(f, "Game {{")?;
+    /// Deserializes a game state from JSON in the given convention.
+    pub fn from_json_origin(json: &str, origin: Origin) -> serde_json::Result<Self> {
+        let game = Self::from_json(json)?;
+        Ok(match origin {
+            Origin::BottomLeft => game,
+            Origin::TopLeft => game.flipped(),
+        })
+    }
 
-        // Grid
-        for y in (0..self.grid.height).rev() {
-            write!(f, "  ")?;
-            for x in 0..self.grid.width {
-                let (cell, hazard) = cells[y * self.grid.width + x];
-                if hazard {
-                    write!(f, "{:?} ", cell.on_bright_black())?;
-                } else {
-                    write!(f, "{cell:?} ")?;
-                }
-            }
-            writeln!(f)?;
+    /// Returns the game flipped vertically, which converts between the top-left
+    /// and bottom-left origins.
+    fn flipped(&self) -> Game {
+        let height = self.grid.height;
+        let mut game = self.clone();
+        for y in 0..height {
+            let row = y * self.grid.width..(y + 1) * self.grid.width;
+            let flipped = (height - 1 - y) * self.grid.width;
+            game.grid.cells[flipped..flipped + self.grid.width]
+                .copy_from_slice(&self.grid.cells[row]);
         }
-
-        // Snakes
-        write!(f, "  Snakes: [")?;
-        let mut first = true;
-        for (id, snake) in self.snakes.iter().enumerate() {
-            if !first {
-                write!(f, ", ")?;
-            } else {
-                first = false;
+        for snake in &mut game.snakes {
+            for p in &mut snake.body {
+                *p = p.to_top_left(height);
             }
-            write!(f, "({id}: {})", snake.health)?;
         }
-        writeln!(f, "]")?;
-
-        writeln!(f, "}}")?;
-
-        Ok(())
+        game
+    }
+
+    /// Writes a replay of the consecutive game states.
+    ///
+    /// Each line contains a state and the moves to the next state as JSON.
+    pub fn write_replay(states: &[Game]) -> String {
+        let mut replay = String::new();
+        for (i, game) in states.iter().enumerate() {
+            let moves = match states.get(i + 1) {
+                Some(next) => game
+                    .snakes
+                    .iter()
+                    .zip(&next.snakes)
+                    .map(|(snake, next)| {
+                        if !snake.alive() || next.body.is_empty() {
+                            return None;
+                        }
+                        Direction::all()
+                            .into_iter()
+                            .find(|&d| game.neighbor(snake.head(), d) == next.head())
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            let frame = ReplayFrame {
+                game: game.clone(),
+                moves,
+            };
+            replay.push_str(&serde_json::to_string(&frame).unwrap());
+            replay.push('\n');
+        }
+        replay
+    }
+
+    /// Reads the game states of a replay (see [`Game::write_replay`]).
+    pub fn read_replay(replay: &str) -> serde_json::Result<Vec<Game>> {
+        replay
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| serde_json::from_str::<ReplayFrame>(l).map(|f| f.game))
+            .collect()
+    }
+
+    /// Renders the board as PNG image with `scale` pixels per cell.
+    ///
+    /// Snakes have the color of their customizations or of their index,
+    /// with lighter heads.
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        const FREE: [u8; 3] = [40, 40, 40];
+        const HAZARD: [u8; 3] = [80, 40, 90];
+        const FOOD: [u8; 3] = [230, 60, 60];
+        const WALL: [u8; 3] = [140, 140, 140];
+        const SNAKES: [[u8; 3]; 5] = [
+            [60, 180, 75],
+            [255, 225, 25],
+            [0, 130, 200],
+            [240, 50, 230],
+            [70, 240, 240],
+        ];
+
+        let (w, h) = (self.grid.width, self.grid.height);
+        let mut cells: Vec<[u8; 3]> = self
+            .grid
+            .cells
+            .iter()
+            .map(|c| match c.t {
+                CellT::Food => FOOD,
+                CellT::Wall => WALL,
+                _ if c.hazard => HAZARD,
+                _ => FREE,
+            })
+            .collect();
+        for (i, snake) in self.snakes.iter().enumerate() {
+            let color = snake.color.unwrap_or(SNAKES[i.min(SNAKES.len() - 1)]);
+            for &p in snake.body.iter().filter(|p| p.within(w, h)) {
+                cells[p.x as usize + p.y as usize * w] = color;
+            }
+            if let Some(head) = snake.body.back().filter(|p| p.within(w, h)) {
+                cells[head.x as usize + head.y as usize * w] = color.map(|c| c / 2 + 128);
+            }
+        }
+
+        // Image rows start at the top
+        let mut pixels = Vec::with_capacity(w * h * scale * scale);
+        for y in (0..h).rev() {
+            for _ in 0..scale {
+                for x in 0..w {
+                    pixels.extend(std::iter::repeat_n(cells[x + y * w], scale));
+                }
+            }
+        }
+        png::encode(w * scale, h * scale, &pixels)
+    }
+
+    /// Writes a PNG image (see [`Game::to_png`]) of each game state into
+    /// `dir`, named after their turns (e.g. `0007.png`), which can be
+    /// stitched to a GIF.
+    ///
+    /// Returns the number of written frames.
+    pub fn write_frames(states: &[Game], dir: &Path, scale: usize) -> io::Result<usize> {
+        fs::create_dir_all(dir)?;
+        for game in states {
+            fs::write(
+                dir.join(format!("{:04}.png", game.turn)),
+                game.to_png(scale),
+            )?;
+        }
+        Ok(states.len())
+    }
+}
+
+impl Debug for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_board(f, true)
+    }
+}
+
+impl Game {
+    /// Returns a stable textual representation without colors,
+    /// which is suitable for snapshot tests.
+    ///
+    /// Hazards are not visible in this representation.
+    pub fn snapshot(&self) -> String {
+        struct Snapshot<'a>(&'a Game);
+        impl Debug for Snapshot<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt_board(f, false)
+            }
+        }
+        format!("{:?}", Snapshot(self))
+    }
+
+    /// Explains the symbols of the board representation of `Debug`.
+    pub fn legend() -> &'static str {
+        "Legend: . free, o food (red), # walls, 0-9 heads, ^ > v < bodies pointing toward the head, \
+         grey background hazards, snakes in their customized colors \
+         (otherwise 0 green, 1 yellow, 2 blue, 3 magenta, others cyan)"
+    }
+
+    /// Renders per cell `values` (indexed like the cells of the grid, e.g. a
+    /// [`Game::distance_map`]) as a heat map for debugging heuristics.
+    ///
+    /// Each cell shows its value scaled to `0`-`9` on a blue (low) to red
+    /// (high) background, the maximum is marked with `*` and non-finite
+    /// values are shown as `.`.
+    pub fn debug_overlay(&self, values: &[f64]) -> String {
+        assert_eq!(values.len(), self.grid.cells.len());
+        let finite = || values.iter().copied().filter(|v| v.is_finite());
+        let min = finite().fold(f64::INFINITY, f64::min);
+        let max = finite().fold(f64::NEG_INFINITY, f64::max);
+
+        let mut txt = String::new();
+        for y in (0..self.grid.height).rev() {
+            for x in 0..self.grid.width {
+                if x > 0 {
+                    txt.push(' ');
+                }
+                let value = values[y * self.grid.width + x];
+                if !value.is_finite() {
+                    txt.push('.');
+                    continue;
+                }
+                let level = if max > min {
+                    (value - min) / (max - min)
+                } else {
+                    1.0
+                };
+                let symbol = if value == max {
+                    '*'
+                } else {
+                    char::from_digit((level * 9.0) as u32, 10).unwrap()
+                };
+                let heat = DynColors::Rgb((255.0 * level) as u8, 0, (255.0 * (1.0 - level)) as u8);
+                txt.push_str(&symbol.on_color(heat).to_string());
+            }
+            txt.push('\n');
+        }
+        txt
+    }
+
+    fn fmt_board(&self, f: &mut fmt::Formatter<'_>, colored: bool) -> fmt::Result {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum FmtCell {
+            Free,
+            Food,
+            Wall,
+            Tail(Direction, DynColors),
+            Head(u8, DynColors),
+        }
+        fn id_color(id: u8) -> AnsiColors {
+            match id {
+                0 => AnsiColors::Green,
+                1 => AnsiColors::Yellow,
+                2 => AnsiColors::Blue,
+                3 => AnsiColors::Magenta,
+                _ => AnsiColors::Cyan,
+            }
+        }
+        impl FmtCell {
+            fn symbol(self) -> char {
+                match self {
+                    FmtCell::Free => '.',
+                    FmtCell::Food => 'o',
+                    FmtCell::Wall => '#',
+                    FmtCell::Tail(dir, _) => match dir {
+                        Direction::Up => '^',
+                        Direction::Right => '>',
+                        Direction::Down => 'v',
+                        Direction::Left => '<',
+                    },
+                    FmtCell::Head(id, _) => char::from_digit(id.into(), 36).unwrap_or('?'),
+                }
+            }
+        }
+        impl Debug for FmtCell {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match *self {
+                    FmtCell::Free | FmtCell::Wall => write!(f, "{}", self.symbol()),
+                    FmtCell::Food => write!(f, "{}", self.symbol().red()),
+                    FmtCell::Tail(_, color) | FmtCell::Head(_, color) => {
+                        write!(f, "{}", self.symbol().color(color))
+                    }
+                }
+            }
+        }
+
+        let mut cells = vec![(FmtCell::Free, false); self.grid.width * self.grid.height];
+        // Broken states may contain segments outside of the board
+        let mut off_board = Vec::new();
+        let index = |p: Vec2D| {
+            p.within(self.grid.width, self.grid.height)
+                .then(|| p.y as usize * self.grid.width + p.x as usize)
+        };
+
+        for y in 0..self.grid.height {
+            for x in 0..self.grid.width {
+                let cell = &mut cells[y * self.grid.width + x];
+                let g_cell = self.grid[Vec2D::new(x as _, y as _)];
+                cell.0 = match g_cell.t {
+                    CellT::Food => FmtCell::Food,
+                    CellT::Wall => FmtCell::Wall,
+                    _ => FmtCell::Free,
+                };
+                cell.1 = g_cell.hazard;
+            }
+        }
+
+        for (id, snake) in self.snakes.iter().enumerate() {
+            if !snake.alive() || snake.body.is_empty() {
+                continue;
+            }
+
+            // Fallback palette for snakes without customizations
+            let color = match snake.color {
+                Some([r, g, b]) => DynColors::Rgb(r, g, b),
+                None => DynColors::Ansi(id_color(id as _)),
+            };
+
+            let mut last_body = *snake.body.front().unwrap();
+
+            for next_body in snake.body.iter().skip(1).copied() {
+                match index(last_body) {
+                    Some(i) => {
+                        cells[i].0 = FmtCell::Tail(Direction::from(next_body - last_body), color)
+                    }
+                    None => off_board.push((id, last_body)),
+                }
+                last_body = next_body;
+            }
+
+            match index(last_body) {
+                Some(i) => cells[i].0 = FmtCell::Head(id as _, color),
+                None => off_board.push((id, last_body)),
+            }
+        }
+        if !off_board.is_empty() {
+            warn!("off-board snake segments at turn {}", self.turn);
+        }
+
+        writeln!(
+            f,
+            "Game turn {} {}x{} {{",
+            self.turn, self.grid.width, self.grid.height
+        )?;
+
+        // Grid
+        for y in (0..self.grid.height).rev() {
+            write!(f, "  ")?;
+            for x in 0..self.grid.width {
+                if x > 0 {
+                    write!(f, " ")?;
+                }
+                let (cell, hazard) = cells[y * self.grid.width + x];
+                if !colored {
+                    write!(f, "{}", cell.symbol())?;
+                } else if hazard {
+                    write!(f, "{:?}", cell.on_bright_black())?;
+                } else {
+                    write!(f, "{cell:?}")?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        // Snakes
+        write!(f, "  Snakes: [")?;
+        let mut first = true;
+        for (id, snake) in self.snakes.iter().enumerate() {
+            if !first {
+                write!(f, ", ")?;
+            } else {
+                first = false;
+            }
+            write!(f, "({id}: {})", snake.health)?;
+        }
+        writeln!(f, "]")?;
+        if !off_board.is_empty() {
+            write!(f, "  Off-board: [")?;
+            for (i, (id, p)) in off_board.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "({id}: {p})")?;
+            }
+            writeln!(f, "]")?;
+        }
+
+        writeln!(f, "}}")?;
+
+        Ok(())
     }
 }
 
@@ -486,12 +1466,82 @@ impl<'a> Iterator for ValidMoves<'a> {
     }
 }
 
+/// Order in which the directions are tried for a last-resort move.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FallbackOrder {
+    /// Shuffled by the turn and snake, so that the fallback is not
+    /// predictable.
+    #[default]
+    Shuffled,
+    /// Always the given order.
+    Fixed([Direction; 4]),
+}
+
+impl FallbackOrder {
+    /// Returns the directions in the order they are tried.
+    pub fn directions(self, game: &Game, snake: u8) -> [Direction; 4] {
+        match self {
+            FallbackOrder::Shuffled => {
+                let mut dirs = Direction::all();
+                let seed = (game.turn as u64) << 8 | snake as u64;
+                dirs.shuffle(&mut SmallRng::seed_from_u64(seed));
+                dirs
+            }
+            FallbackOrder::Fixed(dirs) => dirs,
+        }
+    }
+}
+
+/// Returns the last-resort move of the snake in the [`FallbackOrder::Shuffled`] order.
+pub fn fallback_move(game: &Game, snake: u8) -> Direction {
+    fallback_move_with(game, snake, FallbackOrder::default())
+}
+
+/// Returns the first valid move of the snake in the given order, or the first
+/// direction if every move is fatal.
+pub fn fallback_move_with(game: &Game, snake: u8, order: FallbackOrder) -> Direction {
+    let dirs = order.directions(game, snake);
+    dirs.into_iter()
+        .find(|&d| game.move_is_valid(snake, d))
+        .unwrap_or(dirs[0])
+}
+
 #[cfg(test)]
 mod test {
     use log::info;
 
+    use crate::env::*;
     use crate::logging;
 
+    fn snake(id: &str, body: &[(i16, i16)]) -> Battlesnake {
+        Battlesnake {
+            id: id.into(),
+            name: id.into(),
+            health: 100,
+            body: body.iter().copied().map(Vec2D::from).collect(),
+            shout: String::new(),
+            customizations: Customizations::default(),
+        }
+    }
+
+    /// Creates a request for the first snake.
+    fn request(width: usize, height: usize, snakes: Vec<Battlesnake>) -> GameRequest {
+        GameRequest {
+            game: GameData::default(),
+            turn: 0,
+            board: Board {
+                height,
+                width,
+                food: Vec::new(),
+                hazards: Vec::new(),
+                hazard_zones: Vec::new(),
+                walls: Vec::new(),
+                snakes: snakes.clone(),
+            },
+            you: snakes[0].clone(),
+        }
+    }
+
     #[test]
     fn game_parse() {
         use super::*;
@@ -584,6 +1634,7 @@ mod test {
             assert!(!game.snake_is_alive(0));
             assert!(game.grid[Vec2D::new(5, 8)].t != CellT::Owned);
             assert!(game.snake_is_alive(1));
+            assert!(game.grid[Vec2D::new(6, 8)].t == CellT::Owned);
             assert!(game.grid[Vec2D::new(8, 8)].t == CellT::Owned);
         }
 
@@ -597,37 +1648,1252 @@ mod test {
     }
 
     #[test]
-    fn test_valid_moves() {
+    fn game_from_request_full() {
+        use super::*;
+        logging();
+
+        let snakes = (0..6)
+            .map(|i| snake(&format!("s{i}"), &[(2 * i, 0), (2 * i, 1), (2 * i, 2)]))
+            .collect();
+        let request = request(11, 11, snakes);
+
+        let game = Game::from_request_full(&request);
+        info!("{game:?}");
+        assert_eq!(game.snakes.len(), 6);
+        assert_eq!(game.grid.count(CellT::Owned), 18);
+
+        // Default only keeps the nearest snakes
+        let game = Game::from_request(&request);
+        assert_eq!(game.snakes.len(), 3);
+        for (i, snake) in game.snakes.iter().enumerate() {
+            assert_eq!(snake.head(), Vec2D::new(2 * i as i16, 0));
+        }
+
+        let game = Game::from_request_capped(&request, 2);
+        assert_eq!(game.snakes.len(), 2);
+        assert_eq!(game.snakes[1].head(), Vec2D::new(2, 0));
+    }
+
+    #[test]
+    fn game_from_request_duplicate_ids() {
+        use super::*;
+        logging();
+
+        let snakes = vec![
+            snake("you", &[(0, 0), (0, 1), (0, 2)]),
+            snake("other", &[(2, 0), (2, 1), (2, 2)]),
+            snake("other", &[(4, 0), (4, 1), (4, 2)]),
+            snake("you", &[(6, 0), (6, 1), (6, 2)]),
+            snake("third", &[(8, 0), (8, 1), (8, 2)]),
+        ];
+        let request = request(11, 11, snakes);
+
+        for game in [
+            Game::from_request(&request),
+            Game::from_request_full(&request),
+        ] {
+            info!("{game:?}");
+            let heads: Vec<Vec2D> = game.snakes.iter().map(|s| s.head()).collect();
+            assert_eq!(
+                heads,
+                [Vec2D::new(0, 0), Vec2D::new(2, 0), Vec2D::new(8, 0)]
+            );
+            assert_eq!(game.grid.count(CellT::Owned), 9);
+        }
+
+        // Capped requests keep the nearest of the distinct snakes
+        let game = Game::from_request_capped(&request, 2);
+        assert_eq!(game.snakes.len(), 2);
+        assert_eq!(game.snakes[1].head(), Vec2D::new(2, 0));
+    }
+
+    #[test]
+    fn game_from_request_dead() {
+        use super::*;
+        logging();
+
+        let mut you = snake("you", &[(1, 1), (1, 0), (0, 0)]);
+        you.health = 0;
+        let snakes = (0..6)
+            .map(|i| snake(&format!("s{i}"), &[(2 * i, 4), (2 * i, 5)]))
+            .collect();
+        let mut request = request(11, 11, snakes);
+        request.you = you.clone();
+
+        let game = Game::from_request(&request);
+        info!("{game:?}");
+        assert_eq!(game.snakes.len(), 3);
+        assert!(!game.snake_is_alive(0));
+        assert!(game.snakes[0].body.is_empty());
+        assert_eq!(game.valid_moves(0).count(), 0);
+
+        you.health = 100;
+        you.body.clear();
+        request.you = you;
+        let game = Game::from_request(&request);
+        assert!(!game.snake_is_alive(0));
+        assert_eq!(game.grid.count(CellT::Owned), 4);
+    }
+
+    #[test]
+    fn game_from_request_hazard_zones() {
+        use super::*;
+        use crate::env::Rect;
+        logging();
+
+        let you = snake("you", &[(0, 0), (0, 1), (0, 2)]);
+        let mut request = request(5, 5, vec![you]);
+        request.board.hazards = vec![Vec2D::new(0, 4)];
+        request.board.hazard_zones = vec![
+            Rect {
+                x: 1,
+                y: 1,
+                width: 3,
+                height: 2,
+            },
+            // Clipped to the board
+            Rect {
+                x: 3,
+                y: 3,
+                width: 5,
+                height: 5,
+            },
+        ];
+
+        let game = Game::from_request(&request);
+        info!("{game:?}");
+        let mut hazards = Vec::new();
+        for y in 0..5 {
+            for x in 0..5 {
+                if game.grid.is_hazardous(Vec2D::new(x, y)) {
+                    hazards.push((x, y));
+                }
+            }
+        }
+        assert_eq!(
+            hazards,
+            [
+                (1, 1),
+                (2, 1),
+                (3, 1),
+                (1, 2),
+                (2, 2),
+                (3, 2),
+                (3, 3),
+                (4, 3),
+                (0, 4),
+                (3, 4),
+                (4, 4),
+            ]
+        );
+
+        // Zones are optional in requests
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("\"hazard_zones\":[{\"x\":1,\"y\":1,\"width\":3,\"height\":2}"));
+        request.board.hazard_zones.clear();
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(!json.contains("hazard_zones"));
+        let parsed: GameRequest = serde_json::from_str(&json).unwrap();
+        assert!(parsed.board.hazard_zones.is_empty());
+    }
+
+    #[test]
+    fn game_snake_shout() {
+        use super::*;
+        logging();
+
+        let you = snake("you", &[(1, 1), (1, 0), (0, 0)]);
+        let mut other = snake("other", &[(3, 3), (3, 2), (3, 1)]);
+        other.shout = "coming for you".into();
+        let game = Game::from_request(&request(5, 5, vec![you, other]));
+        assert_eq!(game.snakes[0].shout, None);
+        assert_eq!(game.snakes[1].shout.as_deref(), Some("coming for you"));
+    }
+
+    #[test]
+    fn game_snake_color() {
+        use super::*;
+        logging();
+
+        let mut you = snake("you", &[(1, 1), (1, 0), (0, 0)]);
+        you.customizations.color = "#ff8000".into();
+        let other = snake("other", &[(3, 3), (3, 2), (3, 1)]);
+        let game = Game::from_request(&request(5, 5, vec![you, other]));
+        assert_eq!(game.snakes[0].color, Some([0xff, 0x80, 0x00]));
+        assert_eq!(game.snakes[1].color, None);
+
+        let output = format!("{game:?}");
+        info!("{output}");
+        // Truecolor escape code of the custom color
+        assert!(output.contains("\x1b[38;2;255;128;0m0"));
+        // Fallback palette for the other snake
+        assert!(output.contains(&format!("{}", 1.color(AnsiColors::Yellow))));
+    }
+
+    #[test]
+    fn game_snapshot() {
         use super::*;
         use Direction::*;
         logging();
 
-        let game = Game::parse(
+        let mut game = Game::parse(
             r#"
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . . . . . . . .
-            . . . . v 1 < . . . .
-            . . . . > 0 ^ . . . ."#,
+            . . . . .
+            . 0 < < .
+            . . . . o
+            . . 1 . .
+            . . ^ . ."#,
         )
         .unwrap();
-
-        assert_eq!(
-            game.snakes[0].body,
-            VecDeque::from(vec![Vec2D::new(4, 1), Vec2D::new(4, 0), Vec2D::new(5, 0)])
-        );
+        game.step(&[Down, Right]);
         assert_eq!(
-            game.snakes[1].body,
-            VecDeque::from(vec![Vec2D::new(6, 0), Vec2D::new(6, 1), Vec2D::new(5, 1)])
+            game.snapshot(),
+            r#"Game turn 1 5x5 {
+  . . . . .
+  . v < . .
+  . 0 . . o
+  . . > 1 .
+  . . ^ . .
+  Snakes: [(0: 99), (1: 99)]
+}
+"#
         );
+    }
 
-        info!("{game:?}");
-        assert!([Right].iter().cloned().eq(game.valid_moves(0)));
+    #[test]
+    fn game_debug_header() {
+        use super::*;
+        logging();
+
+        let mut game = Game::standard_start(11, 7, 2, START_HEALTH);
+        game.turn = 42;
+        let output = format!("{game:?}");
+        info!("{output}\n{}", Game::legend());
+        assert!(output.starts_with("Game turn 42 11x7 {\n"));
+        assert!(game.snapshot().starts_with("Game turn 42 11x7 {\n"));
+    }
+
+    #[test]
+    fn game_step_deaths() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . .
+            0 . .
+            ^ . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.step_deaths(&[Left]), [(0, DeathCause::Wall)]);
+
+        let mut game = Game::parse(
+            r#"
+            . 1 .
+            0 ^ .
+            ^ ^ ."#,
+        )
+        .unwrap();
+        assert_eq!(
+            game.step_deaths(&[Right, Right]),
+            [(0, DeathCause::Body(1))]
+        );
+        assert!(game.snake_is_alive(1));
+
+        let mut game = Game::parse(
+            r#"
+            0 < .
+            >*2 ^ ."#,
+        )
+        .unwrap();
+        assert_eq!(game.step_deaths(&[Down]), [(0, DeathCause::SelfCollision)]);
+
+        let mut game = Game::parse(
+            r#"
+            . . . .
+            0 . 1 .
+            ^ . ^ .
+            . . ^ .
+            . . ^ ."#,
+        )
+        .unwrap();
+        assert_eq!(
+            game.step_deaths(&[Right, Left]),
+            [(0, DeathCause::HeadToHead(1))]
+        );
+
+        let mut game = Game::parse(
+            r#"
+            . . .
+            0 . .
+            ^ . ."#,
+        )
+        .unwrap();
+        game.snakes[0].health = 1;
+        assert_eq!(game.step_deaths(&[Up]), [(0, DeathCause::Starvation)]);
+
+        // No deaths
+        let mut game = Game::parse(
+            r#"
+            . . .
+            0 . .
+            ^ . ."#,
+        )
+        .unwrap();
+        assert!(game.step_deaths(&[Up]).is_empty());
+    }
+
+    #[test]
+    fn game_debug_off_board() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . .
+            . 0 .
+            . ^ ."#,
+        )
+        .unwrap();
+        // A broken state, e.g. by a buggy step
+        game.snakes[0].body.push_front(Vec2D::new(1, -1));
+        game.snakes[0].body.push_back(Vec2D::new(1, 3));
+
+        let output = format!("{game:?}");
+        info!("{output}");
+        assert_eq!(
+            game.snapshot(),
+            r#"Game turn 0 3x3 {
+  . . .
+  . ^ .
+  . ^ .
+  Snakes: [(0: 100)]
+  Off-board: [(0: (1,-1)), (0: (1,3))]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn game_outcome_solo() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . .
+            . 0 .
+            . ^ ."#,
+        )
+        .unwrap();
+        assert_eq!(game.outcome(), Outcome::None);
+        game.step(&[Up]);
+        assert_eq!(game.outcome(), Outcome::None);
+        game.step(&[Up]);
+        assert_eq!(game.outcome(), Outcome::Solo(2));
+    }
+
+    #[test]
+    fn game_stacked_tail() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . v .
+            . . 0 .
+            . 1 < ."#,
+        )
+        .unwrap();
+        assert_eq!(
+            game.snakes[1].body,
+            VecDeque::from(vec![Vec2D::new(2, 0), Vec2D::new(2, 0), Vec2D::new(1, 0)])
+        );
+        // The stacked tail does not vacate
+        assert!(!game.move_is_valid(0, Down));
+
+        game.step(&[Down, Left]);
+        info!("{game:?}");
+        assert!(!game.snake_is_alive(0));
+        assert!(game.snake_is_alive(1));
+        assert!(game.grid[Vec2D::new(2, 0)].t == CellT::Owned);
+    }
+
+    #[test]
+    fn game_self_tail() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        // Moving into the own vacating tail is legal
+        let mut game = Game::parse(
+            r#"
+            > v
+            ^ 0"#,
+        )
+        .unwrap();
+        assert!(game.move_is_valid(0, Left));
+        game.step(&[Left]);
+        info!("{game:?}");
+        assert!(game.snake_is_alive(0));
+        assert_eq!(game.snakes[0].head(), v2(0, 0));
+        assert_eq!(game.snakes[0].body.len(), 4);
+        assert!(game.grid[v2(1, 0)].t == CellT::Owned);
+
+        // The tail does not vacate on the turn after eating
+        let mut game = Game::parse(
+            r#"
+            > v
+            ^*2 0"#,
+        )
+        .unwrap();
+        assert!(!game.move_is_valid(0, Left));
+        game.step(&[Left]);
+        info!("{game:?}");
+        assert!(!game.snake_is_alive(0));
+
+        // Eating grows the snake at its tail
+        let mut game = Game::parse(
+            r#"
+            > > v
+            . o 0"#,
+        )
+        .unwrap();
+        game.step(&[Left]);
+        info!("{game:?}");
+        assert!(game.snake_is_alive(0));
+        assert_eq!(game.snakes[0].body.len(), 5);
+        assert!(game.move_is_valid(0, Left));
+        assert!(!game.move_is_valid(0, Up));
+        game.step(&[Up]);
+        assert!(!game.snake_is_alive(0));
+    }
+
+    #[test]
+    fn game_canonical_encode() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . o . . .
+            . . . . .
+            0 < . . 1
+            . . . . ^"#,
+        )
+        .unwrap();
+        // Rotated clockwise
+        let rotated = Game::parse(
+            r#"
+            . 0 . . .
+            . ^ . o .
+            . . . . .
+            . . . . .
+            > 1 . . ."#,
+        )
+        .unwrap();
+        // Reflected horizontally
+        let reflected = Game::parse(
+            r#"
+            . . . . .
+            . . . o .
+            . . . . .
+            1 . . > 0
+            ^ . . . ."#,
+        )
+        .unwrap();
+        info!("{game:?} {rotated:?} {reflected:?}");
+        assert_ne!(game.encode(), rotated.encode());
+        assert_eq!(game.canonical_encode(), rotated.canonical_encode());
+        assert_eq!(game.canonical_encode(), reflected.canonical_encode());
+        for t in 0..8 {
+            assert_eq!(
+                game.canonical_encode(),
+                game.transformed(t).canonical_encode()
+            );
+        }
+
+        // Swapping the snakes is a different position
+        let swapped = Game::parse(
+            r#"
+            . . . . .
+            . o . . .
+            . . . . .
+            1 < . . 0
+            . . . . ^"#,
+        )
+        .unwrap();
+        assert_ne!(game.canonical_encode(), swapped.canonical_encode());
+
+        // Non square boards are rotated as well
+        let game = Game::parse(
+            r#"
+            . . o
+            0 < ."#,
+        )
+        .unwrap();
+        let rotated = Game::parse(
+            r#"
+            0 .
+            ^ .
+            . o"#,
+        )
+        .unwrap();
+        assert_eq!(game.canonical_encode(), rotated.canonical_encode());
+    }
+
+    #[test]
+    fn game_standard_start() {
+        use super::*;
+        logging();
+
+        let game = Game::standard_start(11, 11, 2, START_HEALTH);
+        info!("{game:?}");
+        assert_eq!(game.snakes.len(), 2);
+        assert_eq!(game.snakes[0].body, [Vec2D::new(1, 1); 3]);
+        assert_eq!(game.snakes[1].body, [Vec2D::new(9, 9); 3]);
+        assert_eq!(game.grid.count(CellT::Food), 3);
+        assert!(game.grid[Vec2D::new(5, 5)].t == CellT::Food);
+        assert!(game.grid[Vec2D::new(0, 2)].t == CellT::Food);
+        assert!(game.grid[Vec2D::new(8, 10)].t == CellT::Food);
+
+        let game = Game::standard_start(11, 11, 4, START_HEALTH);
+        info!("{game:?}");
+        let heads: Vec<Vec2D> = game.snakes.iter().map(|s| s.head()).collect();
+        assert_eq!(
+            heads,
+            [
+                Vec2D::new(1, 1),
+                Vec2D::new(9, 9),
+                Vec2D::new(1, 9),
+                Vec2D::new(9, 1)
+            ]
+        );
+        for (i, a) in heads.iter().enumerate() {
+            assert!(!heads[i + 1..].contains(a));
+        }
+        assert_eq!(game.grid.count(CellT::Owned), 4);
+        assert_eq!(game.grid.count(CellT::Food), 5);
+        // Food is exactly two moves away from each snake
+        for &head in &heads {
+            assert!(Direction::all().iter().any(|&a| Direction::all()
+                .iter()
+                .any(|&b| game.grid.has(head.apply(a).apply(b))
+                    && game.grid[head.apply(a).apply(b)].t == CellT::Food)));
+        }
+    }
+
+    #[test]
+    fn game_parse_string() {
+        use super::*;
+        logging();
+
+        let game = Game::standard_start(7, 7, 4, START_HEALTH);
+        let txt = game.to_parse_string();
+        info!("\n{txt}");
+        assert!(txt.lines().nth(1).unwrap().starts_with(". 2*3 . "));
+        let parsed = Game::parse(&txt).unwrap();
+        assert_eq!(parsed.grid, game.grid);
+        assert_eq!(parsed.snakes, game.snakes);
+
+        // Stacked tails after eating
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . o 0 . .
+            . . ^ . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+        game.step(&[Direction::Left]);
+        let txt = game.to_parse_string();
+        info!("\n{txt}");
+        let parsed = Game::parse(&txt).unwrap();
+        assert_eq!(parsed.snakes, game.snakes);
+        assert_eq!(parsed.snakes[0].body.len(), 4);
+
+        // Explicit counts are not padded
+        let game = Game::parse("0*1 . o").unwrap();
+        assert_eq!(game.snakes[0].body.len(), 1);
+    }
+
+    #[test]
+    fn game_head_to_head_policy() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            0 . . . 1
+            ^ . . . ^
+            ^ . . . ^"#,
+        )
+        .unwrap();
+        game.snakes[1].health = 50;
+
+        // The heads meet in the center on the second move
+        let outcome = |game: &Game, policy| {
+            let mut game = game.clone();
+            game.head_to_head = policy;
+            game.step(&[Right, Left]);
+            game.step(&[Right, Left]);
+            info!("{policy:?} {game:?}");
+            (game.snake_is_alive(0), game.snake_is_alive(1))
+        };
+        assert_eq!(outcome(&game, HeadToHeadPolicy::BothDie), (false, false));
+        assert_eq!(outcome(&game, HeadToHeadPolicy::Health), (true, false));
+        assert_eq!(outcome(&game, HeadToHeadPolicy::BothSurvive), (true, true));
+
+        game.snakes[1].health = 100;
+        assert_eq!(outcome(&game, HeadToHeadPolicy::Health), (false, false));
+    }
+
+    #[test]
+    fn game_debug_overlay() {
+        use super::*;
+        logging();
+
+        let game = Game::new(0, 3, 2, vec![], &[], &[]);
+        let values = [0.0, 1.0, 2.0, f64::NAN, 4.0, 8.0];
+        let overlay = game.debug_overlay(&values);
+        info!("\n{overlay}");
+
+        // Strip the colors
+        let mut plain = String::new();
+        let mut escape = false;
+        for c in overlay.chars() {
+            match c {
+                '\x1b' => escape = true,
+                'm' if escape => escape = false,
+                _ if !escape => plain.push(c),
+                _ => {}
+            }
+        }
+        assert_eq!(plain, ". 4 *\n0 1 2\n");
+    }
+
+    #[test]
+    fn game_walkable_tails() {
+        use super::*;
+        logging();
+
+        // Snake 0 follows its moving tail, snake 1 its stacked tail
+        let game = Game::parse(
+            r#"
+            > v . v*2 .
+            ^ 0 . 1 ."#,
+        )
+        .unwrap();
+        info!("{game:?}");
+        assert_eq!(game.snakes[1].body.len(), 3);
+        for (snake, tail, walkable) in [(0, v2(0, 0), true), (1, v2(3, 1), false)] {
+            let head = game.snakes[snake as usize].head();
+            let dir = Direction::all()
+                .into_iter()
+                .find(|&d| game.neighbor(head, d) == tail)
+                .unwrap();
+            assert_eq!(game.is_walkable(tail), walkable);
+            assert_eq!(game.move_is_valid(snake, dir), walkable);
+            let path = game.a_star(head, tail, &[0.0; 4], Heuristic::default());
+            assert_eq!(path.is_some(), walkable);
+        }
+    }
+
+    #[test]
+    fn game_nearest_opponents() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            3 . . . . . .
+            ^ . . . . . .
+            . . . . . . .
+            . . 0 . . . .
+            . . ^ 1 < . 2
+            . . . . . . ^"#,
+        )
+        .unwrap();
+        assert_eq!(game.nearest_opponents(0, 2), vec![1, 3]);
+        assert_eq!(game.nearest_opponents(0, 5), vec![1, 3, 2]);
+        assert_eq!(game.nearest_opponents(2, 1), vec![1]);
+
+        // Snake 2 dies
+        game.step(&[
+            Direction::Up,
+            Direction::Up,
+            Direction::Right,
+            Direction::Right,
+        ]);
+        assert_eq!(game.nearest_opponents(0, 5), vec![1, 3]);
+    }
+
+    #[test]
+    fn game_a_star_via_food() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . o . . .
+            . . . . .
+            0 . . . .
+            ^ . . . o"#,
+        )
+        .unwrap();
+        let (start, target) = (v2(0, 1), v2(4, 4));
+        let direct = game
+            .a_star(start, target, &[0.0; 4], Heuristic::default())
+            .unwrap();
+        info!("{direct:?}");
+        assert!(!direct.iter().any(|&p| game.grid[p].t == CellT::Food));
+
+        let path = game
+            .a_star_via_food(start, target, Heuristic::default(), 0.5)
+            .unwrap();
+        info!("{path:?}");
+        assert_eq!(path.len(), direct.len());
+        assert!(path.contains(&v2(1, 3)));
+        // The food at the bottom would be a detour
+        assert!(!path.contains(&v2(4, 0)));
+    }
+
+    #[test]
+    fn game_fingerprint() {
+        use super::*;
+        logging();
+
+        let game = Game::standard_start(11, 11, 2, START_HEALTH);
+        let fingerprint = game.fingerprint();
+        info!("{fingerprint}");
+        assert_eq!(fingerprint.len(), 8);
+        assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(
+            Game::standard_start(11, 11, 2, START_HEALTH).fingerprint(),
+            fingerprint
+        );
+
+        let mut other = game.clone();
+        other.step(&[Direction::Up, Direction::Up]);
+        assert_ne!(other.fingerprint(), fingerprint);
+        assert_ne!(
+            Game::standard_start(11, 11, 3, START_HEALTH).fingerprint(),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn game_stalemate() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        // Both snakes chase their own tails
+        let mut game = Game::parse(
+            r#"
+            > v v <
+            ^ 0 1 ^"#,
+        )
+        .unwrap();
+        let cycle = [[Left, Right], [Up, Up], [Right, Left], [Down, Down]];
+
+        let mut recent = Vec::new();
+        for moves in cycle.iter().cycle().take(8) {
+            assert!(!game.is_stalemate(&recent));
+            recent.push(game.encode());
+            game.step(moves);
+        }
+        info!("{game:?}");
+        assert!(game.snakes.iter().all(|s| s.alive()));
+        assert!(game.is_stalemate(&recent));
+        assert!(!game.is_stalemate(&recent[4..]));
+    }
+
+    #[test]
+    fn game_clone_into() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . 0 < < .
+            . . . . o
+            . . 1 . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+
+        let mut dst = game.clone();
+        dst.step(&[Down, Right]);
+        dst.step(&[Down, Up]);
+        dst.snakes.pop();
+        let capacity = dst.grid.cells.capacity();
+
+        game.clone_into(&mut dst);
+        assert_eq!(dst.turn, game.turn);
+        assert_eq!(dst.snakes, game.snakes);
+        assert_eq!(dst.grid.width, game.grid.width);
+        assert_eq!(dst.grid.height, game.grid.height);
+        assert!(dst.grid.cells == game.grid.cells);
+        assert_eq!(dst.grid.cells.capacity(), capacity);
+    }
+
+    #[test]
+    fn game_step_starvation() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . 0 < < .
+            . . 1 . .
+            . . ^ . .
+            . . . . ."#,
+        )
+        .unwrap();
+        game.snakes[0].health = 1;
+
+        // Snake 0 starves while snake 1 moves into its body
+        game.step(&[Left, Up]);
+        info!("{game:?}");
+        assert!(!game.snake_is_alive(0));
+        assert!(game.snakes[0].body.is_empty());
+        assert!(game.grid[Vec2D::new(0, 3)].t != CellT::Owned);
+        assert!(game.grid[Vec2D::new(1, 3)].t != CellT::Owned);
+
+        // The starved snake is no obstacle anymore
+        assert!(game.snake_is_alive(1));
+        assert_eq!(game.snakes[1].head(), Vec2D::new(2, 3));
+        assert!(game.grid[Vec2D::new(2, 3)].t == CellT::Owned);
+    }
+
+    #[test]
+    fn game_step_tracked() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . o 0 . .
+            . . ^ . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+        let before = game.grid.clone();
+        let changes = game.step_tracked(&[Direction::Left]);
+        info!("{changes:?}");
+
+        let mut diff = Vec::new();
+        for y in 0..game.grid.height as i16 {
+            for x in 0..game.grid.width as i16 {
+                let p = Vec2D::new(x, y);
+                if before[p] != game.grid[p] {
+                    diff.push((p, before[p], game.grid[p]));
+                }
+            }
+        }
+        assert_eq!(changes, diff);
+        assert_eq!(changes.len(), 2);
+        assert!(changes.contains(&(
+            Vec2D::new(1, 2),
+            Cell::new(CellT::Food, false),
+            Cell::new(CellT::Owned, false)
+        )));
+        assert!(changes.contains(&(
+            Vec2D::new(2, 0),
+            Cell::new(CellT::Owned, false),
+            Cell::new(CellT::Free, false)
+        )));
+
+        // After eating, the stacked tail stays
+        let changes = game.step_tracked(&[Direction::Up]);
+        assert_eq!(
+            changes,
+            [(
+                Vec2D::new(1, 3),
+                Cell::new(CellT::Free, false),
+                Cell::new(CellT::Owned, false)
+            )]
+        );
+    }
+
+    #[test]
+    fn game_replay() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . o 0 . .
+            . . ^ . .
+            . 1 < < ."#,
+        )
+        .unwrap();
+        game.grid[Vec2D::new(4, 3)].hazard = true;
+        game.snakes[1].shout = Some("hello".into());
+
+        let mut states = vec![game.clone()];
+        for moves in [
+            [Direction::Left, Direction::Up],
+            [Direction::Up, Direction::Up],
+        ] {
+            game.step(&moves);
+            states.push(game.clone());
+        }
+
+        let replay = Game::write_replay(&states);
+        info!("{replay}");
+        assert_eq!(replay.lines().count(), 3);
+        assert!(replay
+            .lines()
+            .next()
+            .unwrap()
+            .contains(r#""moves":["left","up"]"#));
+
+        let read = Game::read_replay(&replay).unwrap();
+        assert_eq!(read.len(), states.len());
+        for (read, state) in read.iter().zip(&states) {
+            assert_eq!(read.turn, state.turn);
+            assert_eq!(read.grid, state.grid);
+            assert_eq!(read.snakes, state.snakes);
+        }
+
+        assert!(Game::read_replay("{").is_err());
+    }
+
+    #[test]
+    fn game_json_origin() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . o
+            . . .
+            0 < ."#,
+        )
+        .unwrap();
+        let json = game.to_json_origin(Origin::TopLeft);
+        info!("{json}");
+        let top_left = Game::from_json(&json).unwrap();
+        assert_eq!(top_left.snakes[0].head(), Vec2D::new(0, 2));
+        assert!(top_left.grid[Vec2D::new(2, 0)].t == CellT::Food);
+
+        let read = Game::from_json_origin(&json, Origin::TopLeft).unwrap();
+        assert_eq!(read.grid, game.grid);
+        assert_eq!(read.snakes, game.snakes);
+        assert_eq!(game.to_json_origin(Origin::BottomLeft), game.to_json());
+    }
+
+    #[test]
+    fn test_valid_moves() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . v 1 < . . . .
+            . . . . > 0 ^ . . . ."#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            game.snakes[0].body,
+            VecDeque::from(vec![Vec2D::new(4, 1), Vec2D::new(4, 0), Vec2D::new(5, 0)])
+        );
+        assert_eq!(
+            game.snakes[1].body,
+            VecDeque::from(vec![Vec2D::new(6, 0), Vec2D::new(6, 1), Vec2D::new(5, 1)])
+        );
+
+        info!("{game:?}");
+        assert!([Right].iter().cloned().eq(game.valid_moves(0)));
+    }
+
+    #[test]
+    fn game_fallback_move() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        // Only the tail below is free
+        let game = Game::parse(
+            r#"
+            . > > v .
+            . ^ 0 < .
+            . ^ < . ."#,
+        )
+        .unwrap();
+        info!("{game:?}");
+        assert!(!game.move_is_valid(0, Up));
+
+        for order in [
+            FallbackOrder::Fixed([Up, Right, Left, Down]),
+            FallbackOrder::Fixed([Up, Left, Right, Down]),
+            FallbackOrder::Shuffled,
+        ] {
+            assert_eq!(fallback_move_with(&game, 0, order), Down);
+        }
+        assert_eq!(fallback_move(&game, 0), Down);
+
+        // Without valid moves, the first direction is used
+        let trapped = Game::parse(
+            r#"
+            0 < <
+            v*2 . ^
+            > > ^"#,
+        )
+        .unwrap();
+        assert_eq!(trapped.valid_moves(0).count(), 0);
+        assert_eq!(
+            fallback_move_with(&trapped, 0, FallbackOrder::Fixed([Left, Up, Right, Down])),
+            Left
+        );
+    }
+
+    #[test]
+    fn game_oriented_view() {
+        use super::*;
+        logging();
+
+        // Asymmetric board, so that every rotation differs
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . o . o
+            . . 0 . .
+            . . . . .
+            o . . . 1"#,
+        )
+        .unwrap();
+
+        for heading in Direction::all() {
+            let mut game = game.clone();
+            let head = game.snakes[0].head();
+            let neck = game.neighbor(head, heading.invert());
+            game.snakes[0].body = [neck, neck, head].into();
+            assert_eq!(game.heading(0), Some(heading));
+
+            let view = game.oriented_view(0);
+            info!("{heading:?}: {view:?}");
+            assert_eq!(view.heading(0), Some(Direction::Up));
+            assert_eq!(game.oriented_move(0, Direction::Up), heading);
+
+            let view_head = view.snakes[0].head();
+            for dir in Direction::all() {
+                let abs = game.oriented_move(0, dir);
+                let p = view.neighbor(view_head, dir);
+                let q = game.neighbor(head, abs);
+                assert_eq!(view.grid.has(p), game.grid.has(q));
+                if view.grid.has(p) {
+                    assert_eq!(view.grid[p], game.grid[q]);
+                }
+                assert_eq!(view.move_is_valid(0, dir), game.move_is_valid(0, abs));
+            }
+        }
+
+        // Without a heading, the view is not rotated
+        assert_eq!(game.heading(0), None);
+        assert_eq!(game.oriented_view(0).grid, game.grid);
+        assert_eq!(game.oriented_move(0, Direction::Left), Direction::Left);
+    }
+
+    #[test]
+    fn game_transforms() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . 1 o
+            o 0 . ^ .
+            . ^ . ^ ."#,
+        )
+        .unwrap();
+        game.grid[Vec2D::new(4, 0)].hazard = true;
+
+        let mut rotated = game.clone();
+        for _ in 0..4 {
+            rotated = rotated.rotate90();
+            info!("{rotated:?}");
+        }
+        assert_eq!(rotated.grid, game.grid);
+        assert_eq!(rotated.snakes, game.snakes);
+        let rotated = game.rotate90();
+        assert_eq!((rotated.grid.width, rotated.grid.height), (3, 5));
+        assert!(rotated.grid.is_hazardous(Vec2D::new(0, 0)));
+
+        for reflected in [game.reflect_x().reflect_x(), game.reflect_y().reflect_y()] {
+            assert_eq!(reflected.grid, game.grid);
+            assert_eq!(reflected.snakes, game.snakes);
+        }
+        assert!(game.reflect_x().grid.is_hazardous(Vec2D::new(0, 0)));
+        assert!(game.reflect_y().grid.is_hazardous(Vec2D::new(4, 2)));
+
+        // Moves commute with the transforms
+        type Transform = (fn(&Game) -> Game, fn(&Direction) -> Direction);
+        let transforms: [Transform; 3] = [
+            (Game::reflect_x, Direction::reflect_x),
+            (Game::reflect_y, Direction::reflect_y),
+            (Game::rotate90, Direction::rotate90),
+        ];
+        for (transform, transform_dir) in transforms {
+            for dir in Direction::all() {
+                let mut moved = game.clone();
+                moved.step(&[dir, Direction::Up]);
+                let mut transformed = transform(&game);
+                transformed.step(&[transform_dir(&dir), transform_dir(&Direction::Up)]);
+                assert_eq!(transformed.grid, transform(&moved).grid);
+                assert_eq!(transformed.snakes, transform(&moved).snakes);
+            }
+        }
+        for dir in Direction::all() {
+            assert_eq!(dir.rotate90().rotate90().rotate90().rotate90(), dir);
+            assert_eq!(dir.rotate90().turn_left(), dir);
+            assert_eq!(dir.reflect_x().reflect_x(), dir);
+            assert_eq!(dir.reflect_y().reflect_y(), dir);
+        }
+    }
+
+    #[test]
+    fn game_joint_moves() {
+        use super::*;
+        use std::collections::HashSet;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . . .
+            . ^ . . .
+            . . . . 1
+            . . . . ^"#,
+        )
+        .unwrap();
+        let joint: Vec<Vec<Direction>> = game.joint_moves().collect();
+        info!("{joint:?}");
+        let counts = [game.valid_moves(0).count(), game.valid_moves(1).count()];
+        assert_eq!(counts, [3, 2]);
+        assert_eq!(joint.len(), counts[0] * counts[1]);
+        assert_eq!(joint.iter().collect::<HashSet<_>>().len(), joint.len());
+        for moves in &joint {
+            assert!(game.move_is_valid(0, moves[0]));
+            assert!(game.move_is_valid(1, moves[1]));
+        }
+
+        // Dead snakes have a placeholder
+        let mut game = game;
+        game.snakes[1].health = 0;
+        game.snakes[1].body.clear();
+        let joint: Vec<Vec<Direction>> = game.joint_moves().collect();
+        assert_eq!(joint.len(), 3);
+        assert!(joint.iter().all(|m| m[1] == Direction::Up));
+    }
+
+    #[test]
+    fn game_eat_on_hazard() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . o . .
+            . . 0 . .
+            . . ^ . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+        game.snakes[0].health = 50;
+        game.grid.add_hazards(&[Vec2D::new(2, 3)]);
+        assert!(game.grid[Vec2D::new(2, 3)].t == CellT::Food);
+
+        // Eating wins over the hazard damage
+        game.step(&[Direction::Up]);
+        info!("{game:?}");
+        assert_eq!(game.snakes[0].health, 100);
+        assert_eq!(game.snakes[0].body.len(), 4);
+        assert!(game.grid[Vec2D::new(2, 3)].t == CellT::Owned);
+        assert!(game.grid.is_hazardous(Vec2D::new(2, 3)));
+
+        // The cell stays hazardous after the body left it
+        for dir in [Direction::Right, Direction::Down, Direction::Down] {
+            game.step(&[dir]);
+            assert!(game.grid[Vec2D::new(2, 3)].t == CellT::Owned);
+        }
+        game.step(&[Direction::Left]);
+        info!("{game:?}");
+        assert_eq!(game.snakes[0].health, 96);
+        assert!(game.grid[Vec2D::new(2, 3)].t == CellT::Free);
+        assert!(game.grid.is_hazardous(Vec2D::new(2, 3)));
+    }
+
+    #[test]
+    fn game_walls() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . # . .
+            . 0 # . o
+            . ^ # . .
+            . . . . ."#,
+        )
+        .unwrap();
+        info!("{game:?}");
+        assert_eq!(game.grid.count(CellT::Wall), 3);
+        assert!(!game.move_is_valid(0, Right));
+        assert!(game.move_is_valid(0, Up));
+        assert_eq!(game.flood_fill(Vec2D::new(1, 3)), 17 - 3 + 1);
+        let path = game.a_star(
+            Vec2D::new(1, 2),
+            Vec2D::new(4, 2),
+            &[0.0; 4],
+            Heuristic::default(),
+        );
+        assert_eq!(path.map(|p| p.len()), Some(10));
+        assert!(game.to_parse_string().contains('#'));
+        assert!(game.grid.to_compact().contains('#'));
+        assert_eq!(
+            Grid::from_compact(&game.grid.to_compact()).unwrap(),
+            game.grid
+        );
+
+        let mut moved = game.clone();
+        assert_eq!(moved.step_deaths(&[Right]), [(0, DeathCause::Wall)]);
+        assert_eq!(moved.grid.count(CellT::Wall), 3);
+
+        // Walls of requests
+        let you = snake("you", &[(1, 1), (1, 0), (0, 0)]);
+        let mut request = request(5, 5, vec![you]);
+        request.board.walls = vec![Vec2D::new(2, 1), Vec2D::new(1, 2)];
+        let game = Game::from_request(&request);
+        assert_eq!(game.grid.count(CellT::Wall), 2);
+        assert_eq!(game.valid_moves(0).collect::<Vec<_>>(), [Left]);
     }
 }
@@ -1,21 +1,33 @@
 use std::cmp::Reverse;
 use std::collections::{BinaryHeap, HashMap};
+use std::f64;
 use std::mem::size_of;
 use std::ops::{Index, IndexMut};
-use std::{f64, usize};
 
-use crate::env::{Direction, Vec2D, HAZARD_DAMAGE};
+use crate::env::{Direction, Rect, Vec2D, HAZARD_DAMAGE};
+use crate::profile;
 use crate::util::OrdPair;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum CellT {
     Free,
     Food,
     Owned,
+    /// Permanent obstacle of the map, which is not part of a snake.
+    Wall,
+}
+
+impl CellT {
+    /// Returns if snakes collide with cells of this type (ignoring moving
+    /// tails).
+    #[inline]
+    pub fn is_obstacle(self) -> bool {
+        matches!(self, CellT::Owned | CellT::Wall)
+    }
 }
 
 /// Represents a single tile of the board
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct Cell {
     pub t: CellT,
     pub hazard: bool,
@@ -42,21 +54,105 @@ impl std::fmt::Debug for Cell {
             CellT::Free => write!(f, "{}", "X".blue().style(style)),
             CellT::Food => write!(f, "{}", "o".red().style(style)),
             CellT::Owned => write!(f, "{}", ".".style(style)),
+            CellT::Wall => write!(f, "{}", "#".style(style)),
         }
     }
 }
 
+/// Heuristic that estimates the remaining costs of the A* search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Heuristic {
+    /// Manhattan distance, admissible for grid moves.
+    #[default]
+    Manhattan,
+    /// Euclidean distance.
+    Euclidean,
+    /// No estimate, the search degrades to Dijkstra's algorithm.
+    Dijkstra,
+}
+
+impl Heuristic {
+    /// Returns the estimated costs from `p` to `target`.
+    pub fn estimate(self, p: Vec2D, target: Vec2D) -> f64 {
+        let diff = target - p;
+        match self {
+            Heuristic::Manhattan => diff.manhattan() as f64,
+            Heuristic::Euclidean => (diff.x as f64).hypot(diff.y as f64),
+            Heuristic::Dijkstra => 0.0,
+        }
+    }
+}
+
+/// Additional options of the A* search, see [`Grid::a_star_with`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AStarOptions<'a> {
+    /// Moves onto food are cheaper by this bias (between 0 and 1), so that
+    /// paths clip food on the way if it does not take longer.
+    /// Larger biases also accept short detours over multiple food.
+    /// The heuristic is scaled down accordingly, so that it never
+    /// overestimates the remaining costs.
+    pub food_bias: f64,
+    /// The search stops after expanding this many nodes and then returns the
+    /// partial path to the next node it would expand, which is the most
+    /// promising toward the target.
+    /// Partial paths do not end at the target.
+    pub max_nodes: Option<usize>,
+    /// Collect [`AStarDiagnostics`] of the search, see [`Grid::a_star_search`].
+    pub diagnostics: bool,
+    /// Number of moves until each cell (indexed like the cells of the grid)
+    /// becomes hazardous, so that paths avoid hazards that grow into them
+    /// before they arrive.
+    pub hazard_forecast: Option<&'a [u16]>,
+}
+
+/// Statistics of an A* search, e.g. to find out why a path is slow or bad.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AStarDiagnostics {
+    /// Number of nodes that were expanded.
+    pub expanded: usize,
+    /// Number of cells with known costs, the closed and open set.
+    pub visited: usize,
+    /// If the search stopped at [`AStarOptions::max_nodes`].
+    pub budget_exhausted: bool,
+}
+
+/// Result of [`Grid::a_star_search`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AStarResult {
+    pub path: Option<Vec<Vec2D>>,
+    /// Only with [`AStarOptions::diagnostics`].
+    pub diagnostics: Option<AStarDiagnostics>,
+}
+
 /// The board representation as grid of free and occupied cells.
 ///
 /// This is allows fast access to specific positions on the grid and
 /// if they are occupied by enemies or food.
-#[derive(Clone)]
+///
+/// Grids are equal if their dimensions and cells are equal.
+#[derive(PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct Grid {
     pub width: usize,
     pub height: usize,
     pub cells: Vec<Cell>,
 }
 
+impl Clone for Grid {
+    fn clone(&self) -> Self {
+        Self {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.width = source.width;
+        self.height = source.height;
+        self.cells.clone_from(&source.cells);
+    }
+}
+
 impl Grid {
     /// Creates a new grid with the provided dimensions.
     #[must_use]
@@ -82,6 +178,63 @@ impl Grid {
         }
     }
 
+    /// Glyphs of the compact representation by cell type, without and with
+    /// hazard.
+    const COMPACT: [(CellT, char, char); 4] = [
+        (CellT::Free, '.', ':'),
+        (CellT::Food, 'o', 'O'),
+        (CellT::Owned, 'x', 'X'),
+        (CellT::Wall, '#', '%'),
+    ];
+
+    /// Returns a dense representation with one line per row (top row first)
+    /// and one glyph per cell.
+    ///
+    /// Free cells are `.`, food `o`, owned cells `x` and walls `#` (like in
+    /// [`Game::parse`](crate::game::Game::parse)). On hazards they are `:`,
+    /// `O`, `X` and `%`.
+    pub fn to_compact(&self) -> String {
+        let mut txt = String::with_capacity((self.width + 1) * self.height);
+        for y in (0..self.height).rev() {
+            for cell in &self.cells[y * self.width..(y + 1) * self.width] {
+                let (_, glyph, hazard) = Self::COMPACT
+                    .into_iter()
+                    .find(|(t, ..)| *t == cell.t)
+                    .unwrap();
+                txt.push(if cell.hazard { hazard } else { glyph });
+            }
+            txt.push('\n');
+        }
+        txt
+    }
+
+    /// Parses the representation of [`Grid::to_compact`].
+    ///
+    /// Leading and trailing whitespace of the lines are ignored.
+    /// Returns `None` for unknown glyphs or rows of different lengths.
+    #[must_use]
+    pub fn from_compact(txt: &str) -> Option<Self> {
+        let rows: Vec<&str> = txt.trim().lines().map(str::trim).rev().collect();
+        let width = rows.first()?.chars().count();
+        let mut cells = Vec::with_capacity(width * rows.len());
+        for row in &rows {
+            if row.chars().count() != width {
+                return None;
+            }
+            for c in row.chars() {
+                let cell = Self::COMPACT.into_iter().find_map(|(t, glyph, hazard)| {
+                    (c == glyph || c == hazard).then_some(Cell::new(t, c == hazard))
+                })?;
+                cells.push(cell);
+            }
+        }
+        Some(Self {
+            width,
+            height: rows.len(),
+            cells,
+        })
+    }
+
     /// Clears the grid.
     pub fn clear(&mut self) {
         for c in &mut self.cells {
@@ -98,7 +251,7 @@ impl Grid {
         }
     }
 
-    /// Adds the provided food to the grid.
+    /// Adds the provided food to the grid, keeping the hazards.
     pub fn add_food(&mut self, food: &[Vec2D]) {
         for &p in food {
             if self.has(p) {
@@ -107,7 +260,17 @@ impl Grid {
         }
     }
 
-    /// Adds the provided hazards to the grid.
+    /// Adds the provided walls to the grid.
+    pub fn add_walls(&mut self, walls: &[Vec2D]) {
+        for &p in walls {
+            if self.has(p) {
+                self[p].t = CellT::Wall;
+            }
+        }
+    }
+
+    /// Adds the provided hazards to the grid, keeping the cell types (food
+    /// can be hazardous in royale).
     pub fn add_hazards(&mut self, hazards: &[Vec2D]) {
         for &p in hazards {
             if self.has(p) {
@@ -116,27 +279,152 @@ impl Grid {
         }
     }
 
+    /// Marks the cells of the rectangles as hazardous, cells outside of the
+    /// grid are ignored.
+    pub fn add_hazard_zones(&mut self, zones: &[Rect]) {
+        for zone in zones {
+            for p in zone.cells() {
+                if self.has(p) {
+                    self[p].hazard = true;
+                }
+            }
+        }
+    }
+
     /// Returns if the cell is hazardous.
     pub fn is_hazardous(&self, p: Vec2D) -> bool {
         self.has(p) && self[p].hazard
     }
 
+    /// Returns the free cells (including food) with at most two free
+    /// neighbors, which are the chokepoints and one-wide corridors of the
+    /// board.
+    ///
+    /// Snake bodies (also their tails) and walls are not free.
+    pub fn corridor_cells(&self) -> Vec<Vec2D> {
+        let free = |p: Vec2D| self.has(p) && !self[p].t.is_obstacle();
+        (0..self.height as i16)
+            .flat_map(|y| (0..self.width as i16).map(move |x| Vec2D::new(x, y)))
+            .filter(|&p| {
+                free(p)
+                    && Direction::all()
+                        .iter()
+                        .filter(|&&d| free(p.apply(d)))
+                        .count()
+                        <= 2
+            })
+            .collect()
+    }
+
+    /// Returns the number of cells of the given type.
+    pub fn count(&self, t: CellT) -> usize {
+        self.cells.iter().filter(|c| c.t == t).count()
+    }
+
+    /// Returns the number of hazardous cells.
+    pub fn hazard_count(&self) -> usize {
+        self.cells.iter().filter(|c| c.hazard).count()
+    }
+
     /// Returns if `p` is within the boundaries of this grid.
     #[inline]
     pub fn has(&self, p: Vec2D) -> bool {
         p.within(self.width, self.height)
     }
 
+    /// Returns if the cells between `a` and `b` on the same row or column are
+    /// not owned by any snake.
+    ///
+    /// The endpoints (e.g. two heads) are not checked.
+    /// Points that are not aligned never have a clear line.
+    pub fn line_clear(&self, a: Vec2D, b: Vec2D) -> bool {
+        if a.x != b.x && a.y != b.y {
+            return false;
+        }
+        let step = Vec2D::new((b.x - a.x).signum(), (b.y - a.y).signum());
+        let mut p = a + step;
+        while p != b && p != a {
+            if !self.has(p) || self[p].t.is_obstacle() {
+                return false;
+            }
+            p = p + step;
+        }
+        true
+    }
+
+    /// Moves `p` over the edges onto the grid, as on wrapped boards.
+    #[inline]
+    pub fn wrap(&self, p: Vec2D) -> Vec2D {
+        Vec2D::new(
+            p.x.rem_euclid(self.width as i16),
+            p.y.rem_euclid(self.height as i16),
+        )
+    }
+
     /// Performes an A* search that applies the `first_move_heuristic` as
     /// additional costs for the first move.
+    /// The `heuristic` estimates the remaining costs to the target.
     #[must_use]
     pub fn a_star(
         &self,
         start: Vec2D,
         target: Vec2D,
         first_move_heuristic: &[f64; 4],
+        heuristic: Heuristic,
     ) -> Option<Vec<Vec2D>> {
-        fn make_path(data: &HashMap<Vec2D, (Vec2D, f64)>, target: Vec2D) -> Vec<Vec2D> {
+        self.a_star_with(
+            start,
+            target,
+            first_move_heuristic,
+            heuristic,
+            AStarOptions::default(),
+            |p| !self[p].t.is_obstacle(),
+        )
+    }
+
+    /// Like [`Grid::a_star`], with a custom check for cells that can be
+    /// entered and additional [`AStarOptions`].
+    #[must_use]
+    pub fn a_star_with(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        first_move_heuristic: &[f64; 4],
+        heuristic: Heuristic,
+        options: AStarOptions,
+        walkable: impl Fn(Vec2D) -> bool,
+    ) -> Option<Vec<Vec2D>> {
+        self.a_star_search(
+            start,
+            target,
+            first_move_heuristic,
+            heuristic,
+            options,
+            walkable,
+        )
+        .path
+    }
+
+    /// Like [`Grid::a_star_with`], but also returns the
+    /// [`AStarDiagnostics`] if they are enabled in the options.
+    #[must_use]
+    pub fn a_star_search(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        first_move_heuristic: &[f64; 4],
+        heuristic: Heuristic,
+        options: AStarOptions,
+        walkable: impl Fn(Vec2D) -> bool,
+    ) -> AStarResult {
+        let AStarOptions {
+            food_bias,
+            max_nodes,
+            diagnostics,
+            hazard_forecast,
+        } = options;
+        let food_bias = food_bias.clamp(0.0, 1.0);
+        fn make_path(data: &HashMap<Vec2D, (Vec2D, f64, u16)>, target: Vec2D) -> Vec<Vec2D> {
             let mut path = Vec::new();
             let mut p = target;
             while p.x >= 0 {
@@ -148,40 +436,66 @@ impl Grid {
         }
 
         let mut queue = BinaryHeap::new();
-        let mut data: HashMap<Vec2D, (Vec2D, f64)> = HashMap::new();
-        data.insert(start, (Vec2D::new(-1, -1), 0.0));
+        // predecessor, costs and number of moves from the start
+        let mut data: HashMap<Vec2D, (Vec2D, f64, u16)> = HashMap::new();
+        data.insert(start, (Vec2D::new(-1, -1), 0.0, 0));
+
+        let mut expanded = 0;
+        let result = |path, data: &HashMap<_, _>, expanded, budget_exhausted| AStarResult {
+            path,
+            diagnostics: diagnostics.then_some(AStarDiagnostics {
+                expanded,
+                visited: data.len(),
+                budget_exhausted,
+            }),
+        };
 
         queue.push(OrdPair(Reverse(0), start));
         while let Some(OrdPair(_, front)) = queue.pop() {
-            let cost = data.get(&front).unwrap().1;
+            profile::count(|c| c.a_star_nodes += 1);
+            let (_, cost, steps) = *data.get(&front).unwrap();
 
             if front == target {
-                return Some(make_path(&data, target));
+                return result(Some(make_path(&data, target)), &data, expanded, false);
+            }
+
+            if max_nodes.is_some_and(|max| expanded >= max) {
+                return result(Some(make_path(&data, front)), &data, expanded, true);
             }
+            expanded += 1;
 
             for d in Direction::all() {
                 let neighbor = front.apply(d);
                 let mut neighbor_cost = cost + 1.0;
-                if self.is_hazardous(neighbor) {
+                let forecast_hazard = self.has(neighbor)
+                    && hazard_forecast.is_some_and(|forecast| {
+                        forecast[neighbor.x as usize + neighbor.y as usize * self.width]
+                            <= steps + 1
+                    });
+                if self.is_hazardous(neighbor) || forecast_hazard {
                     neighbor_cost += HAZARD_DAMAGE as f64;
                 }
+                if self.has(neighbor) && self[neighbor].t == CellT::Food {
+                    neighbor_cost -= food_bias;
+                }
                 if front == start {
                     neighbor_cost += first_move_heuristic[d as usize];
                 }
 
-                if self.has(neighbor) && self[neighbor].t != CellT::Owned {
-                    let cost_so_far = data.get(&neighbor).map_or(f64::MAX, |(_, c)| *c);
+                if self.has(neighbor) && walkable(neighbor) {
+                    let cost_so_far = data.get(&neighbor).map_or(f64::MAX, |(_, c, _)| *c);
                     if neighbor_cost < cost_so_far {
-                        data.insert(neighbor, (front, neighbor_cost));
+                        data.insert(neighbor, (front, neighbor_cost, steps + 1));
                         // queue does not accept float
-                        let estimated_cost = neighbor_cost + (neighbor - start).manhattan() as f64;
+                        let estimated_cost = neighbor_cost
+                            + (1.0 - food_bias) * heuristic.estimate(neighbor, target);
                         queue.push(OrdPair(Reverse((estimated_cost * 10.0) as usize), neighbor));
                     }
                 }
             }
         }
 
-        None
+        result(None, &data, expanded, false)
     }
 }
 
@@ -228,7 +542,12 @@ mod test {
         let grid = Grid::new(11, 11);
 
         let path = grid
-            .a_star(Vec2D::new(0, 0), Vec2D::new(1, 1), &[1.0, 0.0, 0.0, 0.0])
+            .a_star(
+                Vec2D::new(0, 0),
+                Vec2D::new(1, 1),
+                &[1.0, 0.0, 0.0, 0.0],
+                Heuristic::Manhattan,
+            )
             .unwrap();
         info!("{:?}", path);
         assert_eq!(path.len(), 3);
@@ -236,6 +555,203 @@ mod test {
         assert_eq!(path[2], Vec2D::new(1, 1));
     }
 
+    #[test]
+    fn grid_eq_hash() {
+        use super::*;
+        use crate::game::Game;
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        logging();
+
+        fn hash(grid: &Grid) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            grid.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let txt = r#"
+            . . o . .
+            . 0 < < .
+            . . . . ."#;
+        let mut game = Game::parse(txt).unwrap();
+        let other = Game::parse(txt).unwrap();
+        assert_eq!(game.grid, other.grid);
+        assert_eq!(hash(&game.grid), hash(&other.grid));
+
+        // The capacity of the cells is irrelevant
+        let mut copy = Grid::new(5, 3);
+        copy.cells.reserve(100);
+        copy.clone_from(&game.grid);
+        assert_eq!(copy, game.grid);
+        assert_eq!(hash(&copy), hash(&game.grid));
+
+        game.step(&[Direction::Up]);
+        assert_ne!(game.grid, other.grid);
+        assert_ne!(hash(&game.grid), hash(&other.grid));
+    }
+
+    #[test]
+    fn grid_line_clear() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            0 . . . 1
+            ^ . v . ^
+            ^ . 2 . ^
+            3 . . . ."#,
+        )
+        .unwrap();
+        let grid = &game.grid;
+        let v = Vec2D::new;
+        // Aligned
+        assert!(grid.line_clear(v(0, 3), v(4, 3)));
+        assert!(grid.line_clear(v(4, 3), v(0, 3)));
+        assert!(grid.line_clear(v(0, 0), v(4, 0)));
+        assert!(grid.line_clear(v(2, 1), v(2, 0)));
+        assert!(grid.line_clear(v(1, 1), v(1, 1)));
+        // Blocked
+        assert!(!grid.line_clear(v(0, 1), v(4, 1)));
+        assert!(!grid.line_clear(v(0, 0), v(0, 3)));
+        assert!(!grid.line_clear(v(2, 3), v(2, 0)));
+        // Not aligned
+        assert!(!grid.line_clear(v(0, 3), v(4, 0)));
+        assert!(!grid.line_clear(v(0, 0), v(1, 3)));
+    }
+
+    #[test]
+    fn grid_compact() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . o
+            . 0 < < .
+            o . 1 . .
+            . . ^ . o"#,
+        )
+        .unwrap();
+        game.grid
+            .add_hazards(&[Vec2D::new(0, 0), Vec2D::new(4, 0), Vec2D::new(2, 1)]);
+
+        let compact = game.grid.to_compact();
+        info!("\n{compact}");
+        assert_eq!(compact, "....o\n.xxx.\no.X..\n:.x.O\n");
+        assert!(Grid::from_compact(&compact).unwrap() == game.grid);
+
+        let grid = Grid::from_compact(
+            r#"
+            .o.
+            #:."#,
+        )
+        .unwrap();
+        assert_eq!((grid.width, grid.height), (3, 2));
+        assert!(grid[Vec2D::new(1, 1)].t == CellT::Food);
+        assert!(grid[Vec2D::new(1, 0)].hazard);
+        assert!(grid[Vec2D::new(0, 0)].t == CellT::Wall);
+
+        assert!(Grid::from_compact("..\n.").is_none());
+        assert!(Grid::from_compact("w").is_none());
+    }
+
+    #[test]
+    fn grid_count() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . o
+            . 0 < < .
+            . . . . .
+            o . 1 . .
+            . . ^ . o"#,
+        )
+        .unwrap();
+        let grid = &mut game.grid;
+        assert_eq!(grid.count(CellT::Food), 3);
+        assert_eq!(grid.count(CellT::Owned), 5);
+        assert_eq!(grid.count(CellT::Free), 17);
+        assert_eq!(grid.hazard_count(), 0);
+
+        // Hazards are independent of the cell type
+        grid.add_hazards(&[Vec2D::new(0, 0), Vec2D::new(4, 0), Vec2D::new(2, 1)]);
+        assert_eq!(grid.hazard_count(), 3);
+        assert_eq!(grid.count(CellT::Food), 3);
+    }
+
+    #[test]
+    fn grid_corridor_cells() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        // A one-wide corridor connects two open areas
+        let game = Game::parse(
+            r#"
+            . . . # # # # . . .
+            . . . . . o . . . .
+            . . . # # # # . . .
+            . . . # # # # . . ."#,
+        )
+        .unwrap();
+        let corridors = game.grid.corridor_cells();
+        info!("{corridors:?}");
+        for x in 3..7 {
+            assert!(corridors.contains(&Vec2D::new(x, 2)));
+        }
+        // Open cells and the corridor entries are not flagged
+        assert!(!corridors.contains(&Vec2D::new(1, 1)));
+        assert!(!corridors.contains(&Vec2D::new(2, 2)));
+        assert!(!corridors.contains(&Vec2D::new(7, 2)));
+        assert!(!corridors.contains(&Vec2D::new(8, 2)));
+        // Obstacles are not corridors either
+        assert!(!corridors.contains(&Vec2D::new(4, 1)));
+        // Besides the corridor, only the corners of the open areas remain
+        assert_eq!(corridors.iter().filter(|p| p.y == 2).count(), 4);
+        assert!(corridors
+            .iter()
+            .all(|p| p.y == 2 || [0, 2, 7, 9].contains(&p.x) && [0, 3].contains(&p.y)));
+    }
+
+    #[test]
+    fn grid_food_on_hazards() {
+        use super::*;
+        logging();
+
+        let food = [Vec2D::new(1, 1), Vec2D::new(2, 0)];
+        let hazards = [Vec2D::new(1, 1), Vec2D::new(0, 0)];
+
+        // The order in which they are added does not matter
+        let mut food_first = Grid::new(3, 3);
+        food_first.add_food(&food);
+        food_first.add_hazards(&hazards);
+        let mut hazards_first = Grid::new(3, 3);
+        hazards_first.add_hazards(&hazards);
+        hazards_first.add_food(&food);
+        hazards_first.add_hazard_zones(&[Rect {
+            x: 1,
+            y: 1,
+            width: 1,
+            height: 1,
+        }]);
+
+        for grid in [&food_first, &hazards_first] {
+            info!("{grid:?}");
+            let cell = grid[Vec2D::new(1, 1)];
+            assert!(cell.t == CellT::Food && cell.hazard);
+            assert_eq!(grid.count(CellT::Food), 2);
+            assert_eq!(grid.hazard_count(), 2);
+            assert_eq!(grid.to_compact(), "...\n.O.\n:.o\n");
+        }
+        assert!(food_first == hazards_first);
+    }
+
     #[test]
     fn grid_a_star_hazards() {
         use super::*;
@@ -248,11 +764,154 @@ mod test {
             Vec2D::new(2, 3),
         ]);
         let path = grid
-            .a_star(Vec2D::new(0, 2), Vec2D::new(4, 2), &[1.0, 1.0, 1.0, 1.0])
+            .a_star(
+                Vec2D::new(0, 2),
+                Vec2D::new(4, 2),
+                &[1.0, 1.0, 1.0, 1.0],
+                Heuristic::Manhattan,
+            )
             .unwrap();
         info!("{:?}", path);
         assert_eq!(path.len(), 9);
         assert_eq!(path[0], Vec2D::new(0, 2));
         assert_eq!(path[path.len() - 1], Vec2D::new(4, 2));
     }
+
+    #[test]
+    fn grid_a_star_heuristics() {
+        use super::*;
+        logging();
+        // Crossing the hazards is shorter but more expensive than the detour
+        let mut grid = Grid::new(7, 5);
+        grid.add_hazards(&[
+            Vec2D::new(3, 0),
+            Vec2D::new(3, 1),
+            Vec2D::new(3, 2),
+            Vec2D::new(3, 3),
+        ]);
+        let cost = |path: &[Vec2D]| {
+            path.iter()
+                .skip(1)
+                .map(|&p| {
+                    if grid.is_hazardous(p) {
+                        1 + HAZARD_DAMAGE as usize
+                    } else {
+                        1
+                    }
+                })
+                .sum::<usize>()
+        };
+
+        let dijkstra = grid
+            .a_star(
+                Vec2D::new(0, 0),
+                Vec2D::new(6, 0),
+                &[0.0; 4],
+                Heuristic::Dijkstra,
+            )
+            .unwrap();
+        info!("{:?}", dijkstra);
+        assert!(dijkstra.iter().all(|&p| !grid.is_hazardous(p)));
+        assert_eq!(cost(&dijkstra), 14);
+
+        // Both heuristics are admissible and find a path with the same costs
+        for heuristic in [Heuristic::Manhattan, Heuristic::Euclidean] {
+            let path = grid
+                .a_star(Vec2D::new(0, 0), Vec2D::new(6, 0), &[0.0; 4], heuristic)
+                .unwrap();
+            info!("{heuristic:?}: {:?}", path);
+            assert_eq!(cost(&path), cost(&dijkstra));
+        }
+    }
+
+    #[test]
+    fn grid_a_star_budget() {
+        use super::*;
+        logging();
+        // Walls with alternating gaps at the top and bottom
+        let mut grid = Grid::new(7, 7);
+        for (x, gap) in [(1, 6), (3, 0), (5, 6)] {
+            for y in (0..7).filter(|&y| y != gap) {
+                grid[Vec2D::new(x, y)].t = CellT::Owned;
+            }
+        }
+        let (start, target) = (Vec2D::new(0, 0), Vec2D::new(6, 0));
+        let search = |max_nodes| {
+            grid.a_star_with(
+                start,
+                target,
+                &[0.0; 4],
+                Heuristic::Manhattan,
+                AStarOptions {
+                    max_nodes,
+                    ..AStarOptions::default()
+                },
+                |p| grid[p].t != CellT::Owned,
+            )
+            .unwrap()
+        };
+
+        let full = search(None);
+        info!("{full:?}");
+        assert_eq!(full.len(), 31);
+        assert_eq!(search(Some(100)), full);
+
+        // The partial path follows the corridor toward the target
+        let partial = search(Some(10));
+        info!("{partial:?}");
+        assert_eq!(partial.len(), 11);
+        assert_eq!(partial, full[..partial.len()]);
+        assert_ne!(partial.last(), Some(&target));
+    }
+
+    #[test]
+    fn grid_a_star_diagnostics() {
+        use super::*;
+        logging();
+
+        let mut grid = Grid::new(7, 7);
+        for y in 0..6 {
+            grid[Vec2D::new(3, y)].t = CellT::Owned;
+        }
+        let (start, target) = (Vec2D::new(0, 0), Vec2D::new(6, 0));
+        let search = |grid: &Grid, max_nodes, diagnostics| {
+            grid.a_star_search(
+                start,
+                target,
+                &[0.0; 4],
+                Heuristic::Manhattan,
+                AStarOptions {
+                    max_nodes,
+                    diagnostics,
+                    ..AStarOptions::default()
+                },
+                |p| grid[p].t != CellT::Owned,
+            )
+        };
+
+        // Without the flag, only the path is returned
+        let plain = search(&grid, None, false);
+        assert!(plain.diagnostics.is_none());
+
+        let full = search(&grid, None, true);
+        let diagnostics = full.diagnostics.unwrap();
+        info!("{diagnostics:?}");
+        assert_eq!(full.path, plain.path);
+        assert!(diagnostics.expanded > 0);
+        assert!(diagnostics.visited >= diagnostics.expanded);
+        assert!(!diagnostics.budget_exhausted);
+
+        let partial = search(&grid, Some(5), true);
+        let diagnostics = partial.diagnostics.unwrap();
+        info!("{diagnostics:?}");
+        assert_eq!(diagnostics.expanded, 5);
+        assert!(diagnostics.budget_exhausted);
+        assert_ne!(partial.path.unwrap().last(), Some(&target));
+
+        // Unreachable targets expand every reachable cell
+        grid[Vec2D::new(3, 6)].t = CellT::Owned;
+        let none = search(&grid, None, true);
+        assert!(none.path.is_none());
+        assert_eq!(none.diagnostics.unwrap().expanded, 3 * 7);
+    }
 }
@@ -4,16 +4,47 @@ use std::io::Write;
 
 // Exported to be accessable in benchmarks
 pub mod agents;
+pub mod diff;
 pub mod env;
+pub mod eval;
 pub mod game;
 pub mod grid;
+pub mod playout;
+mod png;
+pub mod prelude;
+pub mod profile;
+pub mod server;
 mod util;
 
+/// Initializes the logger with the info level, unless `RUST_LOG` is set.
 pub fn logging() {
-    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .is_test(cfg!(test))
-        .format(logging_format)
-        .try_init();
+    logging_verbose(0);
+}
+
+/// Initializes the logger with the level of the verbosity flags
+/// (see [`verbosity_level`]), unless `RUST_LOG` is set.
+///
+/// Release builds only log up to the info level.
+pub fn logging_verbose(verbosity: u8) {
+    let _ = logger(verbosity).try_init();
+}
+
+/// Returns the log level for the number of verbosity flags:
+/// info by default, debug for `-v` and trace for `-vv`.
+pub fn verbosity_level(verbosity: u8) -> log::LevelFilter {
+    match verbosity {
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
+fn logger(verbosity: u8) -> env_logger::Builder {
+    let level = verbosity_level(verbosity).to_string();
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level));
+    builder.is_test(cfg!(test)).format(logging_format);
+    builder
 }
 
 fn logging_format(buf: &mut Formatter, record: &log::Record) -> std::io::Result<()> {
@@ -38,3 +69,25 @@ fn logging_format(buf: &mut Formatter, record: &log::Record) -> std::io::Result<
         .color(color)
     )
 }
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn logging_verbosity() {
+        use super::*;
+        use log::LevelFilter;
+        logging();
+
+        assert_eq!(verbosity_level(0), LevelFilter::Info);
+        assert_eq!(verbosity_level(1), LevelFilter::Debug);
+        assert_eq!(verbosity_level(2), LevelFilter::Trace);
+        assert_eq!(verbosity_level(5), LevelFilter::Trace);
+
+        // RUST_LOG takes precedence over the verbosity
+        if std::env::var_os("RUST_LOG").is_none() {
+            assert_eq!(logger(0).build().filter(), LevelFilter::Info);
+            assert_eq!(logger(1).build().filter(), LevelFilter::Debug);
+            assert_eq!(logger(2).build().filter(), LevelFilter::Trace);
+        }
+    }
+}
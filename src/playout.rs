@@ -0,0 +1,838 @@
+use std::collections::{HashMap, VecDeque};
+
+use log::{debug, warn};
+use rand::prelude::*;
+use rand::seq::IteratorRandom;
+
+use crate::agents::{Agent, RandomAgent};
+use crate::env::*;
+use crate::game::{DeathCause, Game, Outcome, Snake, START_HEALTH};
+use crate::grid::CellT;
+
+/// Number of recent positions that are checked for repetitions.
+const HISTORY_LEN: usize = 32;
+
+/// Configuration of the simulated games.
+#[derive(Debug, Clone)]
+pub struct Playout {
+    /// Time each snake has for a turn.
+    pub timeout: u64,
+    /// Chance new food spawns.
+    pub food_rate: f64,
+    /// Number of turns after which the hazard expands.
+    pub shrink_turns: usize,
+    /// Number of times a position has to occur until the game is a match.
+    /// Zero disables the repetition detection.
+    pub repetitions: usize,
+    /// Keep playing after all opponents died until the last snake dies too,
+    /// like in rulesets where the winner plays out the rest.
+    /// The last snake is the winner either way, but by default the game ends
+    /// as soon as it is the only survivor.
+    pub play_out: bool,
+}
+
+impl Default for Playout {
+    fn default() -> Self {
+        Self {
+            timeout: 200,
+            food_rate: 0.15,
+            shrink_turns: 25,
+            repetitions: 3,
+            play_out: false,
+        }
+    }
+}
+
+impl Playout {
+    /// Plays the game until it ends and returns the outcome.
+    pub async fn play_game(
+        &self,
+        agents: &[Agent],
+        game: &mut Game,
+        rng: &mut SmallRng,
+    ) -> Outcome {
+        self.play_game_recorded(agents, game, rng, None, None).await
+    }
+
+    /// Like [`Playout::play_game`], but pushes the game state of every turn
+    /// (including the initial and final states) to `states` and the snakes
+    /// that died with their cause to `deaths`.
+    pub async fn play_game_recorded(
+        &self,
+        agents: &[Agent],
+        game: &mut Game,
+        rng: &mut SmallRng,
+        mut states: Option<&mut Vec<Game>>,
+        mut deaths: Option<&mut Vec<(u8, DeathCause)>>,
+    ) -> Outcome {
+        let mut food_count = 4;
+
+        debug!("init: {game:?}");
+
+        let mut hazard_insets = [0; 4];
+        let mut history = VecDeque::with_capacity(HISTORY_LEN);
+        // The last survivor of a multiplayer game, if it plays out
+        let mut winner = None;
+
+        for turn in game.turn.. {
+            if let Some(states) = states.as_deref_mut() {
+                states.push(game.clone());
+            }
+
+            let mut moves = [Direction::Up; 4];
+            for i in 0..game.snakes.len() {
+                if game.snakes[i].alive() {
+                    // Agents assume player 0 is you.
+                    game.snakes.swap(0, i);
+
+                    let response = agents[i].step_internal(self.timeout, game).await;
+                    moves[i] = response.r#move;
+
+                    game.snakes.swap(0, i);
+                }
+            }
+            debug!("Moves: {moves:?}");
+
+            for (snake, cause) in game.step_deaths(&moves) {
+                debug!("snake {snake} died: {cause:?}");
+                if let Some(deaths) = deaths.as_deref_mut() {
+                    deaths.push((snake, cause));
+                }
+            }
+
+            debug!("{}: {:?}", turn, game);
+
+            let mut outcome = game.outcome();
+            if self.play_out {
+                if let Outcome::Winner(survivor) = outcome {
+                    if winner.is_none() {
+                        debug!("snake {survivor} plays out the game");
+                    }
+                    winner = Some(survivor);
+                    outcome = Outcome::None;
+                } else if let Some(survivor) = winner {
+                    outcome = Outcome::Winner(survivor);
+                }
+            }
+            if outcome != Outcome::None {
+                if let Some(states) = states.as_deref_mut() {
+                    states.push(game.clone());
+                }
+                warn!("game: {outcome:?} after {turn} turns");
+                return outcome;
+            }
+
+            // Check for repetitions
+            if self.repetitions > 0 {
+                let hash = game.encode();
+                if history.iter().filter(|&&h| h == hash).count() + 1 >= self.repetitions {
+                    if let Some(states) = states.as_deref_mut() {
+                        states.push(game.clone());
+                    }
+                    warn!("game: repetition after {turn} turns");
+                    return winner.map_or(Outcome::Match, Outcome::Winner);
+                }
+                if history.len() == HISTORY_LEN {
+                    history.pop_front();
+                }
+                history.push_back(hash);
+            }
+
+            // Check if snakes have consumed food
+            for snake in &game.snakes {
+                if snake.alive() && snake.health == 100 {
+                    food_count -= 1;
+                }
+            }
+
+            // Spawn food
+            if food_count == 0 || rng.gen::<f64>() < self.food_rate {
+                if let Some(cell) = game
+                    .grid
+                    .cells
+                    .iter_mut()
+                    .filter(|c| c.t == CellT::Free)
+                    .choose(rng)
+                {
+                    cell.t = CellT::Food;
+                    food_count += 1;
+                }
+            }
+
+            // Hazards
+            if turn > 0
+                && turn % self.shrink_turns == 0
+                && hazard_insets[0] + hazard_insets[2] < game.grid.height
+                && hazard_insets[1] + hazard_insets[3] < game.grid.width
+            {
+                let dir = rng.gen_range(0..4);
+                hazard_insets[dir] += 1;
+                if dir % 2 == 0 {
+                    let y = if dir == 0 {
+                        hazard_insets[dir] - 1
+                    } else {
+                        game.grid.height - hazard_insets[dir]
+                    };
+                    for x in 0..game.grid.width {
+                        game.grid[v2(x as _, y as _)].hazard = true;
+                    }
+                } else {
+                    let x = if dir == 1 {
+                        hazard_insets[dir] - 1
+                    } else {
+                        game.grid.width - hazard_insets[dir]
+                    };
+                    for y in 0..game.grid.height {
+                        game.grid[v2(x as _, y as _)].hazard = true;
+                    }
+                }
+            }
+        }
+        winner.map_or(Outcome::Match, Outcome::Winner)
+    }
+}
+
+/// Results of an agent against a baseline, see [`Playout::ladder`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ladder {
+    pub wins: usize,
+    pub losses: usize,
+    /// Games without a winner, e.g. repetitions or simultaneous deaths.
+    pub draws: usize,
+    /// Number of deaths of the agent by their cause.
+    pub deaths: HashMap<DeathCause, usize>,
+}
+
+impl Ladder {
+    /// Number of played games.
+    pub fn games(&self) -> usize {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Share of the won games, draws count as half a win.
+    pub fn win_rate(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+    }
+
+    /// Returns the 95% confidence interval of the win rate
+    /// (Wilson score interval).
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        const Z: f64 = 1.96;
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return (0.0, 1.0);
+        }
+        let p = self.win_rate();
+        let center = (p + Z * Z / (2.0 * n)) / (1.0 + Z * Z / n);
+        let margin = Z / (1.0 + Z * Z / n) * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt();
+        ((center - margin).max(0.0), (center + margin).min(1.0))
+    }
+}
+
+impl Playout {
+    /// Plays `games` games of the agent against the baseline on boards of the
+    /// given size, swapping their start positions every other game.
+    ///
+    /// The agents and games are seeded with `seed`, so the results are
+    /// reproducible.
+    pub async fn ladder(
+        &self,
+        agent: &Agent,
+        baseline: &Agent,
+        games: usize,
+        width: usize,
+        height: usize,
+        seed: u64,
+    ) -> Ladder {
+        let mut ladder = Ladder::default();
+        let mut rng = SmallRng::seed_from_u64(seed);
+        for i in 0..games {
+            // The agent plays snake `me`
+            let me = i % 2;
+            let mut agents = [
+                agent.clone().seeded(seed),
+                baseline.clone().seeded(seed + 1),
+            ];
+            agents.rotate_left(me);
+
+            let mut game = init_game(width, height, 2, START_HEALTH, &mut rng);
+            let mut deaths = Vec::new();
+            let outcome = self
+                .play_game_recorded(&agents, &mut game, &mut rng, None, Some(&mut deaths))
+                .await;
+            match outcome {
+                Outcome::Winner(winner) if winner as usize == me => ladder.wins += 1,
+                Outcome::Winner(_) => ladder.losses += 1,
+                _ => ladder.draws += 1,
+            }
+            for (_, cause) in deaths.into_iter().filter(|&(s, _)| s as usize == me) {
+                *ladder.deaths.entry(cause).or_default() += 1;
+            }
+        }
+        ladder
+    }
+}
+
+/// Turn of a [`Playout::timeline`].
+#[derive(Debug, Clone)]
+pub struct TimelineStep {
+    /// Board before the move.
+    pub game: Game,
+    pub r#move: Direction,
+    pub rationale: String,
+}
+
+impl Playout {
+    /// Steps the agent (snake 0) through the game until it ends or
+    /// `max_turns` are played, while the opponents follow the default
+    /// [`RolloutPolicy`].
+    ///
+    /// Returns every turn and the final board.
+    /// Unlike [`Playout::play_game`], the rules do not spawn food or hazards.
+    pub async fn timeline(
+        &self,
+        agent: &Agent,
+        game: &Game,
+        max_turns: usize,
+        rng: &mut SmallRng,
+    ) -> (Vec<TimelineStep>, Game) {
+        let policy = RolloutPolicy::default();
+        let mut game = game.clone();
+        let mut steps = Vec::new();
+        while steps.len() < max_turns && game.snake_is_alive(0) && game.outcome() == Outcome::None {
+            let response = agent.step_internal(self.timeout, &game).await;
+            let mut moves = vec![Direction::Up; game.snakes.len()];
+            moves[0] = response.r#move;
+            for (i, m) in moves.iter_mut().enumerate().skip(1) {
+                *m = policy.next_move(&game, i as u8, rng);
+            }
+
+            steps.push(TimelineStep {
+                game: game.clone(),
+                r#move: response.r#move,
+                rationale: response.rationale,
+            });
+            game.step(&moves);
+        }
+        (steps, game)
+    }
+}
+
+/// Move policy of the fast simulations (rollouts) that estimate the value of
+/// a position, e.g. for a tree search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RolloutPolicy {
+    /// Below this health the snakes follow a shortest path to the nearest
+    /// food instead of moving randomly, so that rollouts do not end early by
+    /// starvation.
+    /// Zero disables the food seeking.
+    pub food_health: u8,
+}
+
+impl Default for RolloutPolicy {
+    fn default() -> Self {
+        Self { food_health: 50 }
+    }
+}
+
+impl RolloutPolicy {
+    /// Returns the move of the snake, which is random unless it is hungry
+    /// (see [`Game::food_moves`]).
+    pub fn next_move(&self, game: &Game, snake: u8, rng: &mut SmallRng) -> Direction {
+        let my = &game.snakes[snake as usize];
+        if my.alive() && my.health < self.food_health {
+            if let Some(&dir) = game.food_moves(snake).choose(rng) {
+                return dir;
+            }
+        }
+        RandomAgent::random_move(game, snake, rng)
+    }
+
+    /// Plays the game with this policy for all snakes until it ends or
+    /// `max_turns` are played and returns the outcome.
+    pub fn rollout(&self, game: &mut Game, max_turns: usize, rng: &mut SmallRng) -> Outcome {
+        for _ in 0..max_turns {
+            let mut moves = vec![Direction::Up; game.snakes.len()];
+            for (i, m) in moves.iter_mut().enumerate() {
+                *m = self.next_move(game, i as u8, rng);
+            }
+            game.step(&moves);
+
+            let outcome = game.outcome();
+            if outcome != Outcome::None {
+                return outcome;
+            }
+        }
+        Outcome::None
+    }
+}
+
+/// Creates a new game with randomized start positions and food.
+///
+/// The snakes start with `health` ([`START_HEALTH`](crate::game::START_HEALTH) in the
+/// standard rules).
+pub fn init_game(
+    width: usize,
+    height: usize,
+    num_agents: usize,
+    health: u8,
+    rng: &mut SmallRng,
+) -> Game {
+    if width.is_multiple_of(2) || height.is_multiple_of(2) {
+        warn!("If the dimension are even, the initial board configuration is unfair!");
+    }
+    if width != height {
+        warn!("If width != height, the initial board configuration is unfair!");
+    }
+
+    // Either start in the corners or in the middle of the edges
+    let mut start_positions = if rng.gen() {
+        // Corners
+        [
+            v2(1, 1),
+            v2((width - 2) as _, 1),
+            v2((width - 2) as _, (height - 2) as _),
+            v2(1, (height - 2) as _),
+        ]
+    } else {
+        // Edges
+        [
+            v2((width / 2) as _, 1),
+            v2((width - 2) as _, (height / 2) as _),
+            v2((width / 2) as _, (height - 2) as _),
+            v2(1, (height / 2) as _),
+        ]
+    }
+    .into_iter()
+    .choose_multiple(rng, num_agents);
+
+    start_positions.shuffle(rng);
+
+    let snakes = start_positions
+        .into_iter()
+        .map(|p| Snake::new(vec![p; 3].into(), health))
+        .collect();
+
+    let mut game = Game::new(0, width, height, snakes, &[], &[]);
+
+    // Food at center
+    game.grid[(width / 2, height / 2).into()].t = CellT::Food;
+
+    // Spawn 1 food 2 steps away from each snake
+    for snake in game.snakes.clone() {
+        let p = [v2(-1, -1), v2(-1, 1), v2(1, 1), v2(1, -1)]
+            .into_iter()
+            .map(|p| snake.head() + p)
+            // Only free cells on the board
+            .filter(|&p| game.grid.has(p) && !game.grid[p].t.is_obstacle())
+            // Limit to a border cells (excluding the corners)
+            .filter(|&p| {
+                (p.x == 0 || p.x == game.grid.width as i16 - 1)
+                    ^ (p.y == 0 || p.y == game.grid.height as i16 - 1)
+            })
+            .choose(rng);
+        if let Some(p) = p {
+            game.grid[p].t = CellT::Food;
+        }
+    }
+
+    game
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn playout_repetition() {
+        use super::*;
+        use crate::agents::RandomAgent;
+        logging();
+
+        // Both snakes can only chase their own tails
+        let game = Game::parse(
+            r#"
+            > v v <
+            ^ 0 1 ^"#,
+        )
+        .unwrap();
+        info!("{game:?}");
+        let agents = [
+            Agent::Random(RandomAgent::default()),
+            Agent::Random(RandomAgent::default()),
+        ];
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let playout = Playout {
+            food_rate: 0.0,
+            shrink_turns: 1000,
+            ..Playout::default()
+        };
+        let mut repeating = game.clone();
+        let outcome = playout.play_game(&agents, &mut repeating, &mut rng).await;
+        assert_eq!(outcome, Outcome::Match);
+        // The position repeats every four turns
+        assert_eq!(repeating.turn, 9);
+        assert!(repeating.snakes.iter().all(|s| s.alive()));
+
+        // Without repetition detection, they play until they starve
+        let playout = Playout {
+            repetitions: 0,
+            ..playout
+        };
+        let mut starving = game.clone();
+        let outcome = playout.play_game(&agents, &mut starving, &mut rng).await;
+        assert_eq!(outcome, Outcome::Match);
+        assert_eq!(starving.turn, 100);
+    }
+
+    #[test]
+    fn playout_rollout_food() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            o . . . . . . . . . o
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . 0 . . . . .
+            . . . . . ^ . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            o . . . . . . . . . o"#,
+        )
+        .unwrap();
+        game.snakes[0].health = 30;
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let mut average = |policy: RolloutPolicy| {
+            let mut turns = 0;
+            for _ in 0..50 {
+                let mut game = game.clone();
+                match policy.rollout(&mut game, 500, &mut rng) {
+                    Outcome::Solo(t) => turns += t,
+                    _ => turns += 500,
+                }
+            }
+            turns as f64 / 50.0
+        };
+        let random = average(RolloutPolicy { food_health: 0 });
+        let seeking = average(RolloutPolicy::default());
+        info!("random: {random}, seeking: {seeking}");
+        assert!(seeking > 2.0 * random);
+    }
+
+    #[test]
+    fn playout_rollout_food_path() {
+        use super::*;
+        logging();
+
+        // The direct way to the food is blocked by walls
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . # . . . .
+            . 0 # o . . .
+            . ^ # # # . .
+            . ^ . . . . ."#,
+        )
+        .unwrap();
+        game.snakes[0].health = 30;
+        assert_eq!(game.food_moves(0), vec![Direction::Up]);
+
+        let policy = RolloutPolicy::default();
+        for seed in 0..10 {
+            let mut game = game.clone();
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut path = Vec::new();
+            while game.snakes[0].health < 100 {
+                let dir = policy.next_move(&game, 0, &mut rng);
+                path.push(dir);
+                game.step(&[dir]);
+                assert!(game.snake_is_alive(0));
+            }
+            info!("{path:?}");
+            use Direction::*;
+            assert_eq!(path, [Up, Up, Right, Right, Down, Down]);
+        }
+    }
+
+    #[test]
+    fn playout_rollout_many_snakes() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . 1 < . . 2 < . . 3 <
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . 0 . . . . .
+            . . . . . ^ . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . 4 < . . . . . . 5 <
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let outcome = RolloutPolicy::default().rollout(&mut game, 500, &mut rng);
+        info!("{outcome:?}: {game:?}");
+        assert_ne!(outcome, Outcome::None);
+    }
+
+    #[tokio::test]
+    async fn playout_last_survivor() {
+        use super::*;
+        logging();
+
+        // Snake 1 has no valid moves
+        let game = Game::parse(
+            r#"
+            1 < . . . . .
+            >*2 ^ . . . . .
+            . . . . . . .
+            . . . 0 . . .
+            . . . ^ . . .
+            . . . ^ . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        let agents = [
+            Agent::Random(RandomAgent::default().seeded(0)),
+            Agent::Random(RandomAgent::default().seeded(1)),
+        ];
+
+        // Ends as soon as snake 0 is the only survivor
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut states = Vec::new();
+        let outcome = Playout::default()
+            .play_game_recorded(
+                &agents,
+                &mut game.clone(),
+                &mut rng,
+                Some(&mut states),
+                None,
+            )
+            .await;
+        assert_eq!(outcome, Outcome::Winner(0));
+        assert_eq!(states.len(), 2);
+        assert!(states[1].snake_is_alive(0));
+
+        // Plays out the rest
+        let playout = Playout {
+            play_out: true,
+            repetitions: 0,
+            ..Playout::default()
+        };
+        let mut states = Vec::new();
+        let mut end = game.clone();
+        let outcome = playout
+            .play_game_recorded(&agents, &mut end, &mut rng, Some(&mut states), None)
+            .await;
+        info!("{end:?}");
+        assert_eq!(outcome, Outcome::Winner(0));
+        assert!(states.len() > 2);
+        assert!(!end.snake_is_alive(0));
+    }
+
+    #[tokio::test]
+    async fn playout_solo() {
+        use super::*;
+        use crate::agents::StarAgent;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . 0 . .
+            . . ^ . .
+            . . . . ."#,
+        )
+        .unwrap();
+        game.snakes[0].health = 10;
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let playout = Playout {
+            food_rate: 0.0,
+            repetitions: 0,
+            ..Playout::default()
+        };
+        let agents = [Agent::AStar(StarAgent::default())];
+        let outcome = playout.play_game(&agents, &mut game, &mut rng).await;
+        info!("{game:?}");
+        // Starves, as there is no food
+        assert_eq!(outcome, Outcome::Solo(10));
+    }
+
+    #[tokio::test]
+    async fn playout_start_health() {
+        use super::*;
+        use crate::agents::SurvivorAgent;
+        use crate::game::START_HEALTH;
+        logging();
+
+        let playout = Playout {
+            food_rate: 0.0,
+            shrink_turns: 1000,
+            repetitions: 0,
+            ..Playout::default()
+        };
+        let agents = [Agent::Survivor(SurvivorAgent)];
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let mut turns = Vec::new();
+        for health in [START_HEALTH, 50] {
+            let mut game = Game::standard_start(11, 11, 1, health);
+            assert!(game.snakes[0].health == health);
+            for y in 0..11 {
+                for x in 0..11 {
+                    if game.grid[v2(x, y)].t == CellT::Food {
+                        game.grid[v2(x, y)].t = CellT::Free;
+                    }
+                }
+            }
+            let outcome = playout.play_game(&agents, &mut game, &mut rng).await;
+            info!("{health}: {outcome:?}");
+            let Outcome::Solo(t) = outcome else {
+                panic!("expected a solo game")
+            };
+            turns.push(t);
+        }
+        // Without food, the snakes starve after their starting health
+        assert_eq!(turns, [START_HEALTH as usize, 50]);
+    }
+
+    #[tokio::test]
+    async fn playout_frames() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . 0 . . .
+            . ^ . . .
+            . ^ . . ."#,
+        )
+        .unwrap();
+        let mut starving = game.clone();
+        starving.snakes[0].health = 3;
+
+        let playout = Playout {
+            food_rate: 0.0,
+            ..Playout::default()
+        };
+        let agents = [Agent::Random(RandomAgent::default().seeded(0))];
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut states = Vec::new();
+        let outcome = playout
+            .play_game_recorded(&agents, &mut starving, &mut rng, Some(&mut states), None)
+            .await;
+        info!("{outcome:?}");
+        // The initial state and the states after the three moves
+        assert_eq!(states.len(), 4);
+
+        let dir = std::env::temp_dir().join(format!("hadar-frames-{}", std::process::id()));
+        assert_eq!(Game::write_frames(&states, &dir, 4).unwrap(), 4);
+        let mut files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, ["0000.png", "0001.png", "0002.png", "0003.png"]);
+    }
+
+    #[tokio::test]
+    async fn playout_ladder() {
+        use super::*;
+        logging();
+
+        let playout = Playout {
+            timeout: 50,
+            ..Playout::default()
+        };
+        let agent = Agent::default();
+        let baseline = Agent::Random(RandomAgent::default());
+        let ladder = playout.ladder(&agent, &baseline, 4, 7, 7, 1).await;
+        info!("{ladder:?}");
+        assert_eq!(ladder.games(), 4);
+        assert!(ladder.wins > ladder.losses);
+
+        let (low, high) = ladder.confidence_interval();
+        info!("win rate {} [{low}, {high}]", ladder.win_rate());
+        assert!((0.0..=1.0).contains(&ladder.win_rate()));
+        assert!(low <= ladder.win_rate() && ladder.win_rate() <= high);
+        assert!(ladder.deaths.values().sum::<usize>() <= ladder.losses + ladder.draws);
+
+        // Reproducible with the same seed
+        assert_eq!(playout.ladder(&agent, &baseline, 4, 7, 7, 1).await, ladder);
+    }
+
+    #[tokio::test]
+    async fn playout_timeline() {
+        use super::*;
+        logging();
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let game = init_game(7, 7, 2, START_HEALTH, &mut rng);
+        let agent = Agent::default().seeded(0);
+        let (steps, end) = Playout::default()
+            .timeline(&agent, &game, 5, &mut rng)
+            .await;
+        for step in &steps {
+            info!("{:?}: {:?} ({})", step.game, step.r#move, step.rationale);
+        }
+        info!("{end:?}");
+
+        assert_eq!(steps.len(), 5);
+        for (turn, step) in steps.iter().enumerate() {
+            assert_eq!(step.game.turn, turn);
+            assert!(step.game.move_is_valid(0, step.r#move));
+        }
+        assert_eq!(end.turn, 5);
+        assert!(end.snake_is_alive(0));
+
+        // Stops when the game ends
+        let game = Game::parse(". . > 0").unwrap();
+        let (steps, end) = Playout::default()
+            .timeline(&agent, &game, 5, &mut rng)
+            .await;
+        assert_eq!(steps.len(), 1);
+        assert_eq!(end.outcome(), Outcome::Solo(1));
+
+        // More than four snakes
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . 1 < . . 2 < . . 3 <
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . 0 . . . . .
+            . . . . . ^ . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . 4 < . . . . . . 5 <
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+        let (steps, end) = Playout::default()
+            .timeline(&agent, &game, 2, &mut rng)
+            .await;
+        assert_eq!(steps.len(), 2);
+        assert_eq!(end.snakes.len(), 6);
+    }
+}
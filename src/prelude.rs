@@ -0,0 +1,29 @@
+//! Re-exports of the most commonly used types.
+//!
+//! ```
+//! use hadar::prelude::*;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let game = Game::parse(
+//!     r#"
+//!     . . . o .
+//!     . . . . .
+//!     . 0 . . .
+//!     . ^ . . .
+//!     . ^ . . ."#,
+//! )
+//! .unwrap();
+//! assert_eq!(game.outcome(), Outcome::None);
+//!
+//! let step: MoveResponse = Agent::default().step_internal(200, &game).await;
+//! assert!(game.move_is_valid(0, step.r#move));
+//! let next: Vec2D = game.snakes[0].head().apply(step.r#move);
+//! assert!(game.grid.has(next));
+//! # }
+//! ```
+
+pub use crate::agents::Agent;
+pub use crate::env::{Direction, GameRequest, MoveResponse, Vec2D};
+pub use crate::game::{Game, Outcome, Snake};
+pub use crate::grid::{CellT, Grid};
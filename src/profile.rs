@@ -0,0 +1,103 @@
+//! Per-step profiling counters, which are enabled with the `profiling` feature.
+//!
+//! The counters are thread-local and reset at the beginning of every agent
+//! step. Without the feature, counting is a no-op and all counters are zero.
+
+/// Work done since the last [`reset`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Nodes expanded by the A* search.
+    pub a_star_nodes: u64,
+    /// Cells visited by flood fills and distance maps.
+    pub flood_fill_cells: u64,
+    /// Cloned games.
+    pub game_clones: u64,
+    /// Evaluated moves or positions.
+    pub evaluations: u64,
+}
+
+#[cfg(feature = "profiling")]
+thread_local! {
+    static COUNTERS: std::cell::Cell<Counters> = std::cell::Cell::new(Counters::default());
+}
+
+/// Resets all counters of this thread.
+pub fn reset() {
+    #[cfg(feature = "profiling")]
+    COUNTERS.set(Counters::default());
+}
+
+/// Returns the counters of this thread.
+pub fn counters() -> Counters {
+    #[cfg(feature = "profiling")]
+    return COUNTERS.get();
+    #[cfg(not(feature = "profiling"))]
+    Counters::default()
+}
+
+/// Updates the counters of this thread.
+#[cfg(feature = "profiling")]
+#[inline]
+pub(crate) fn count(f: impl FnOnce(&mut Counters)) {
+    COUNTERS.with(|c| {
+        let mut counters = c.get();
+        f(&mut counters);
+        c.set(counters);
+    });
+}
+
+/// Updates the counters of this thread.
+#[cfg(not(feature = "profiling"))]
+#[inline(always)]
+pub(crate) fn count(_f: impl FnOnce(&mut Counters)) {}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[cfg(feature = "profiling")]
+    #[tokio::test]
+    async fn profile_counters() {
+        use super::*;
+        use crate::agents::{Agent, RandomAgent};
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . o
+            . . . . .
+            0 < . . .
+            . . . . ."#,
+        )
+        .unwrap();
+
+        let _ = Agent::default().step_internal(200, &game).await;
+        let counters = counters();
+        info!("{counters:?}");
+        assert!(counters.a_star_nodes > 0);
+        assert!(counters.flood_fill_cells > 0);
+        assert!(counters.evaluations > 0);
+
+        let _ = Agent::Random(RandomAgent::default())
+            .step_internal(200, &game)
+            .await;
+        assert_eq!(super::counters(), Counters::default());
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    #[test]
+    fn profile_disabled() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse("0 . o").unwrap();
+        reset();
+        let _ = game.clone().ranked_moves(0);
+        info!("{:?}", counters());
+        assert_eq!(counters(), Counters::default());
+    }
+}
@@ -0,0 +1,378 @@
+//! Routes of the battlesnake web server.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Instant;
+
+use log::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use warp::{Filter, Rejection, Reply};
+
+use crate::agents::Agent;
+use crate::env::{GameRequest, IndexResponse, API_VERSION};
+use crate::game::Game;
+
+pub const PACKAGE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Runtime server configuration.
+pub struct State {
+    /// Time in ms that is subtracted from the game timeouts.
+    pub latency: u64,
+    pub color: String,
+    pub head: String,
+    pub tail: String,
+    pub author: String,
+    pub config: Agent,
+    /// Turn and cancellation token of the in-flight steps by game id.
+    steps: Mutex<HashMap<String, (usize, CancellationToken)>>,
+    metrics: Metrics,
+    /// Sender of the served requests to the writer of the record.
+    record: Option<mpsc::Sender<GameRequest>>,
+}
+
+impl State {
+    pub fn new(
+        latency: u64,
+        color: String,
+        head: String,
+        tail: String,
+        author: String,
+        config: Agent,
+    ) -> Self {
+        Self {
+            latency,
+            color,
+            head,
+            tail,
+            author,
+            config,
+            steps: Mutex::default(),
+            metrics: Metrics::default(),
+            record: None,
+        }
+    }
+
+    /// Writes every served request as JSON line into the sink.
+    ///
+    /// The requests are written on a separate thread, so that slow sinks do
+    /// not delay the moves.
+    pub fn with_record(mut self, mut sink: Box<dyn Write + Send>) -> Self {
+        let (sender, receiver) = mpsc::channel::<GameRequest>();
+        std::thread::spawn(move || {
+            for request in receiver {
+                if let Err(e) = serde_json::to_writer(&mut sink, &request)
+                    .map_err(std::io::Error::from)
+                    .and_then(|_| writeln!(sink))
+                    .and_then(|_| sink.flush())
+                {
+                    error!("recording request failed: {e}");
+                }
+            }
+        });
+        self.record = Some(sender);
+        self
+    }
+
+    fn record(&self, request: GameRequest) {
+        if let Some(sender) = &self.record {
+            // The writer runs as long as the server
+            let _ = sender.send(request);
+        }
+    }
+}
+
+/// Counters that are updated by the handlers.
+#[derive(Debug, Default)]
+struct Metrics {
+    games_started: AtomicU64,
+    games_ended: AtomicU64,
+    moves: AtomicU64,
+    /// Total decision time in µs.
+    latency: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+/// Metrics of the server, served at `/metrics`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MetricsReport {
+    pub games_started: u64,
+    pub games_ended: u64,
+    pub moves: u64,
+    /// Average decision time of the moves in ms.
+    pub average_latency: f64,
+    /// Number of moves that took longer than the game timeout (minus the
+    /// latency).
+    pub timeouts: u64,
+}
+
+impl Metrics {
+    fn report(&self) -> MetricsReport {
+        let moves = self.moves.load(Ordering::Relaxed);
+        let latency = self.latency.load(Ordering::Relaxed);
+        MetricsReport {
+            games_started: self.games_started.load(Ordering::Relaxed),
+            games_ended: self.games_ended.load(Ordering::Relaxed),
+            moves,
+            average_latency: if moves > 0 {
+                latency as f64 / moves as f64 / 1000.0
+            } else {
+                0.0
+            },
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Returns all routes of the server.
+pub fn routes(
+    state: Arc<State>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let index = warp::get()
+        .and(warp::path::end())
+        .and(with_state(state.clone()))
+        .map(|state: Arc<State>| {
+            warn!("index");
+            warp::reply::json(&IndexResponse::new(
+                API_VERSION,
+                &state.author,
+                &state.color,
+                &state.head,
+                &state.tail,
+                PACKAGE_VERSION,
+            ))
+        });
+
+    let start = warp::path("start")
+        .and(warp::post())
+        .and(warp::body::json::<GameRequest>())
+        .and(with_state(state.clone()))
+        .and_then(start);
+
+    let r#move = warp::path("move")
+        .and(warp::post())
+        .and(warp::body::json::<GameRequest>())
+        .and(with_state(state.clone()))
+        .and_then(step);
+
+    let end = warp::path("end")
+        .and(warp::post())
+        .and(warp::body::json::<GameRequest>())
+        .and(with_state(state.clone()))
+        .map(|request: GameRequest, state: Arc<State>| {
+            warn!("end {request}");
+            state.metrics.games_ended.fetch_add(1, Ordering::Relaxed);
+            if let Some((_, token)) = state.steps.lock().unwrap().remove(&request.game.id) {
+                token.cancel();
+            }
+            state.record(request);
+            warp::reply()
+        });
+
+    let metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(with_state(state))
+        .map(|state: Arc<State>| warp::reply::json(&state.metrics.report()));
+
+    index.or(start).or(r#move).or(end).or(metrics)
+}
+
+fn with_state(
+    config: Arc<State>,
+) -> impl Filter<Extract = (Arc<State>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+async fn start(request: GameRequest, state: Arc<State>) -> Result<impl warp::Reply, Infallible> {
+    warn!("start {request}");
+    state.metrics.games_started.fetch_add(1, Ordering::Relaxed);
+    state.config.on_start(&request);
+    state.record(request);
+    Ok(warp::reply())
+}
+
+async fn step(request: GameRequest, state: Arc<State>) -> Result<impl warp::Reply, Infallible> {
+    warn!("move {request}");
+
+    let fingerprint = Game::from_request(&request).fingerprint();
+
+    // Cancel the stale step of this game
+    let cancel = CancellationToken::new();
+    if let Some((_, stale)) = state
+        .steps
+        .lock()
+        .unwrap()
+        .insert(request.game.id.clone(), (request.turn, cancel.clone()))
+    {
+        stale.cancel();
+    }
+
+    let timer = Instant::now();
+    let next_move = state
+        .config
+        .step(&request, state.latency, Some(&cancel))
+        .await;
+    let elapsed = timer.elapsed();
+
+    {
+        let mut steps = state.steps.lock().unwrap();
+        if steps
+            .get(&request.game.id)
+            .is_some_and(|(turn, _)| *turn == request.turn)
+        {
+            steps.remove(&request.game.id);
+        }
+    }
+
+    let metrics = &state.metrics;
+    metrics.moves.fetch_add(1, Ordering::Relaxed);
+    metrics
+        .latency
+        .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    if elapsed.as_millis() as u64 > request.game.timeout.saturating_sub(state.latency) {
+        metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    info!(
+        "response time {:?}ms [{}]: {:?} ({})",
+        elapsed.as_millis(),
+        fingerprint,
+        next_move.r#move,
+        next_move.rationale
+    );
+
+    state.record(request);
+    Ok(warp::reply::json(&next_move))
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn server_metrics() {
+        use super::*;
+        logging();
+
+        let state = Arc::new(State::new(
+            0,
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            Agent::default(),
+        ));
+        let routes = routes(state);
+
+        let request = r#"{
+            "game": {"id": "game", "timeout": 500},
+            "turn": 0,
+            "board": {
+                "height": 5,
+                "width": 5,
+                "food": [{"x": 4, "y": 4}],
+                "hazards": [],
+                "snakes": [{"id": "you", "name": "you", "health": 100, "body": [{"x": 2, "y": 2}]}]
+            },
+            "you": {"id": "you", "name": "you", "health": 100, "body": [{"x": 2, "y": 2}]}
+        }"#;
+        let post = |path: &'static str| {
+            warp::test::request()
+                .method("POST")
+                .path(path)
+                .body(request)
+        };
+
+        assert_eq!(post("/start").reply(&routes).await.status(), 200);
+        for _ in 0..2 {
+            let response = post("/move").reply(&routes).await;
+            assert_eq!(response.status(), 200);
+            info!("{:?}", response.body());
+        }
+        assert_eq!(post("/end").reply(&routes).await.status(), 200);
+
+        let response = warp::test::request().path("/metrics").reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        let report: MetricsReport = serde_json::from_slice(response.body()).unwrap();
+        info!("{report:?}");
+        assert_eq!(report.games_started, 1);
+        assert_eq!(report.games_ended, 1);
+        assert_eq!(report.moves, 2);
+        assert_eq!(report.timeouts, 0);
+        assert!(report.average_latency >= 0.0);
+    }
+
+    #[tokio::test]
+    async fn server_record() {
+        use super::*;
+        logging();
+
+        /// Shared buffer to inspect the recorded requests.
+        #[derive(Clone, Default)]
+        struct Sink(Arc<Mutex<Vec<u8>>>);
+        impl Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sink = Sink::default();
+        let state = Arc::new(
+            State::new(
+                0,
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                Agent::default(),
+            )
+            .with_record(Box::new(sink.clone())),
+        );
+        let routes = routes(state);
+
+        let request = r#"{
+            "game": {"id": "game", "timeout": 500},
+            "turn": 3,
+            "board": {
+                "height": 5,
+                "width": 5,
+                "food": [],
+                "hazards": [],
+                "snakes": [{"id": "you", "name": "you", "health": 100, "body": [{"x": 2, "y": 2}]}]
+            },
+            "you": {"id": "you", "name": "you", "health": 100, "body": [{"x": 2, "y": 2}]}
+        }"#;
+        let response = warp::test::request()
+            .method("POST")
+            .path("/move")
+            .body(request)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+
+        // The request is written in the background
+        let mut recorded = String::new();
+        for _ in 0..100 {
+            recorded = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+            if recorded.ends_with('\n') {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        info!("{recorded}");
+        let lines = recorded.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1);
+        let recorded: GameRequest = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(recorded.game.id, "game");
+        assert_eq!(recorded.turn, 3);
+        assert_eq!(recorded.board.snakes[0].body.len(), 1);
+    }
+}
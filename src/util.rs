@@ -1,4 +1,9 @@
 use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Mutex;
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
 
 /// Wrapper for a key-value pair that is ordable by the key.
 #[derive(Debug)]
@@ -23,3 +28,61 @@ impl<K: Ord, V> Ord for OrdPair<K, V> {
         self.0.cmp(&other.0)
     }
 }
+
+/// Random number generator of an agent.
+///
+/// Clones continue with a copy of the current state, so that every agent
+/// (e.g. in parallel playouts) has its own independent stream.
+pub struct AgentRng(Mutex<SmallRng>);
+
+impl AgentRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self(Mutex::new(SmallRng::seed_from_u64(seed)))
+    }
+
+    /// Calls `f` with the generator.
+    pub fn with<R>(&self, f: impl FnOnce(&mut SmallRng) -> R) -> R {
+        f(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Default for AgentRng {
+    fn default() -> Self {
+        Self(Mutex::new(SmallRng::from_entropy()))
+    }
+}
+
+impl Clone for AgentRng {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+impl fmt::Debug for AgentRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AgentRng")
+    }
+}
+
+/// 128 bit FNV-1a hash, which is stable across runs and platforms.
+pub struct Fnv1a(u128);
+
+impl Fnv1a {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    pub fn new() -> Self {
+        Self(Self::OFFSET)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u128 {
+        self.0
+    }
+}
@@ -0,0 +1,94 @@
+//! Regression suite of tactical positions with known good moves.
+//!
+//! To add a position, append a [`Position`] to [`POSITIONS`] with a short
+//! name, the agent config (JSON, empty for the default agent), the board in
+//! the format of [`Game::parse`] and every move that is acceptable.
+//! Snake 0 is the agent and the agents are seeded, so results are stable.
+
+use log::info;
+
+use hadar::agents::Agent;
+use hadar::env::Direction::{self, *};
+use hadar::game::Game;
+use hadar::logging;
+
+struct Position {
+    name: &'static str,
+    config: &'static str,
+    board: &'static str,
+    moves: &'static [Direction],
+}
+
+const POSITIONS: &[Position] = &[
+    Position {
+        name: "trap ahead",
+        config: "",
+        // The food lures into a pocket that is smaller than the body
+        board: r#"
+            . . . . . .
+            v < < < . .
+            v o . 0 . .
+            > > > ^ . ."#,
+        moves: &[Up, Right],
+    },
+    Position {
+        name: "food race",
+        config: "",
+        board: r#"
+            . . . . . . .
+            . . . o . . .
+            . . . . . . .
+            . . . 0 . . 1
+            . . . ^ . . ^
+            . . . ^ . . .
+            . . . . . . ."#,
+        moves: &[Up],
+    },
+    Position {
+        name: "head to head with longer enemy",
+        config: "",
+        board: r#"
+            . . . . .
+            . . . . .
+            . 0 o 1 .
+            . ^ . ^ .
+            . ^ . ^ <"#,
+        moves: &[Up, Left],
+    },
+    Position {
+        name: "box-in opportunity",
+        config: r#"{"AStar": {"aggressive": true, "guard_hunt": true}}"#,
+        // Cutting off snake 1 at the wall traps it
+        board: r#"
+            . . . . .
+            . 0 . . .
+            1 ^ . . .
+            ^ ^ . . .
+            ^ ^ . . ."#,
+        moves: &[Left],
+    },
+];
+
+#[tokio::test]
+async fn tactics() {
+    logging();
+
+    let mut failures = Vec::new();
+    for position in POSITIONS {
+        let agent: Agent = if position.config.is_empty() {
+            Agent::default()
+        } else {
+            position.config.parse().unwrap()
+        };
+        let game = Game::parse(position.board).unwrap();
+        let step = agent.seeded(0).step_internal(200, &game).await;
+        info!("{}: {:?} ({})", position.name, step.r#move, step.rationale);
+        if !position.moves.contains(&step.r#move) {
+            failures.push(format!(
+                "{}: {:?} instead of {:?} ({})",
+                position.name, step.r#move, position.moves, step.rationale
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{failures:#?}");
+}